@@ -23,18 +23,27 @@ use {
     quote::quote,
     std::ffi::{CString, NulError},
     syn::{
-        AngleBracketedGenericArguments, Expr, ExprLit, FnArg, GenericArgument, Ident, ItemFn, Lit,
-        MetaNameValue, PatType, Path, PathArguments, PathSegment, Receiver, ReturnType, Signature,
-        Token, Type, TypeArray, TypePath, TypeReference, Visibility,
+        AngleBracketedGenericArguments, Attribute, Expr, ExprLit, FnArg, GenericArgument, Ident,
+        Item, ItemEnum, ItemFn, ItemStruct, Lit, Meta, MetaNameValue, Pat, PatIdent,
+        PatTupleStruct, PatType, Path, PathArguments, PathSegment, Receiver, Signature, Token,
+        Type, TypeArray, TypePath, TypeReference, Visibility,
         parse::{Parse, ParseStream},
         punctuated::Punctuated,
         spanned::Spanned,
     },
 };
 
+/// The module that bindings are defined in when no `module` argument is given.
+const DEFAULT_MODULE: &str = "empl";
+/// The most parameters that a function can have before `clippy::too_many_arguments` is triggered.
+const MAX_ARGUMENTS: usize = 7;
+
 struct Config {
     struct_ident: Ident,
     guile_ident: CString,
+    module: CString,
+    /// The text of the doc comments on the function.
+    doc: Option<String>,
     fn_ident: Ident,
     inputs: Inputs,
     vis: Visibility,
@@ -44,22 +53,161 @@ impl From<Config> for TokenStream2 {
         Config {
             struct_ident,
             guile_ident,
+            module,
+            doc,
             fn_ident,
             inputs:
                 Inputs {
                     required,
                     optional,
                     rest,
+                    params,
                 },
             vis,
         }: Config,
     ) -> TokenStream2 {
+        let doc = match doc {
+            Some(doc) => quote!(::core::option::Option::Some(#doc)),
+            None => quote!(::core::option::Option::None),
+        };
         let make_args = |arg_count, name| {
             (0..arg_count).map(move |i| Ident::new(&format!("{name}_{i}"), Span::call_site()))
         };
         let required_args = make_args(required, "required").collect::<Vec<_>>();
         let optional_args = make_args(optional, "optional").collect::<Vec<_>>();
         let rest_arg = make_args(rest.into(), "rest").collect::<Vec<_>>();
+        // `call` takes the api along with every argument of the driver, which is not linted since it is `extern "C"`.
+        let call_lint = (1 + required_args.len() + optional_args.len() + rest_arg.len()
+            > MAX_ARGUMENTS)
+            .then(|| quote!(#[expect(clippy::too_many_arguments)]));
+
+        let param_names = match &params {
+            Params::Arrays => (1..=required + optional)
+                .map(|i| format!("arg{i}"))
+                .chain(rest.then(|| "rest".to_string()))
+                .collect::<Vec<_>>(),
+            Params::Typed(params) => params
+                .iter()
+                .enumerate()
+                .map(
+                    |(i, TypedParam { name, .. })| match name.trim_start_matches('_') {
+                        "" => format!("arg{}", i + 1),
+                        name => RenameRule::KebabCase.apply_to_field(name),
+                    },
+                )
+                .collect(),
+        };
+
+        let keywords = match &params {
+            Params::Arrays => Vec::new(),
+            Params::Typed(params) => params
+                .iter()
+                .zip(&param_names)
+                .filter(|(TypedParam { kind, .. }, _)| *kind == ParamKind::Keyword)
+                .map(|(_, name)| name.clone())
+                .collect(),
+        };
+
+        let call = match params {
+            Params::Arrays => quote! {
+                let output = #fn_ident(
+                    api,
+                    [#(crate::guile::Scm::new(#required_args)),*],
+                    [#({
+                        if #optional_args == unsafe { crate::guile::sys::REEXPORTS_SCM_UNDEFINED } {
+                            ::core::option::Option::None
+                        } else {
+                            ::core::option::Option::Some(crate::guile::Scm::new(#optional_args))
+                        }
+                    }),*],
+                    #(crate::guile::Scm::new(#rest_arg),)*
+                );
+            },
+            Params::Typed(params) => {
+                let vars = make_args(params.len(), "arg").collect::<Vec<_>>();
+                let keyword_vars = make_args(keywords.len(), "keyword").collect::<Vec<_>>();
+                let parse_keywords =
+                    rest_arg
+                        .first()
+                        .filter(|_| !keywords.is_empty())
+                        .map(|rest| {
+                            quote! {
+                                let [#(#keyword_vars),*] = crate::guile::convert::keyword_arguments(
+                                    api,
+                                    crate::guile::Scm::new(#rest),
+                                    [#(#keywords),*],
+                                )
+                                .map_err(|error| error.in_procedure(#guile_ident))?;
+                            }
+                        });
+
+                let mut positional_args =
+                    required_args.iter().chain(&optional_args).chain(&rest_arg);
+                let mut keyword_vars = keyword_vars.iter();
+                let conversions = params
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, TypedParam { name, ty, kind, default })| {
+                        let position = i + 1;
+                        let convert = |scm: TokenStream2| {
+                            let conversion = match ty.as_ref() {
+                                // The arguments stay on the stack for the whole call, so borrowed foreign objects cannot be collected.
+                                Type::Reference(TypeReference {
+                                    mutability: None,
+                                    elem,
+                                    ..
+                                }) => quote! {
+                                    unsafe { api.foreign_ref::<#elem>(#scm) }
+                                },
+                                ty => quote! {
+                                    <#ty as crate::guile::convert::FromScm>::from_scm(api, #scm)
+                                },
+                            };
+                            quote! {
+                                #conversion.map_err(|error| error.in_argument(#guile_ident, #position, #name))?
+                            }
+                        };
+
+                        match kind {
+                            ParamKind::Keyword => {
+                                let var = keyword_vars.next();
+                                let conversion = convert(quote!(#var));
+                                let default = default.map_or_else(
+                                    || quote!(::core::default::Default::default()),
+                                    |default| quote!(#default),
+                                );
+                                quote! {
+                                    match #var {
+                                        ::core::option::Option::Some(#var) => #conversion,
+                                        ::core::option::Option::None => #default,
+                                    }
+                                }
+                            }
+                            kind => {
+                                let arg = positional_args.next();
+                                let conversion = convert(quote!(crate::guile::Scm::new(#arg)));
+                                match kind {
+                                    ParamKind::Optional => quote! {
+                                        if #arg == unsafe { crate::guile::sys::REEXPORTS_SCM_UNDEFINED } {
+                                            ::core::option::Option::None
+                                        } else {
+                                            #conversion
+                                        }
+                                    },
+                                    _ => conversion,
+                                }
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                quote! {
+                    #parse_keywords
+                    #(let #vars = #conversions;)*
+                    let output = #fn_ident(api, #(#vars),*);
+                }
+            }
+        };
 
         quote! {
             #vis struct #struct_ident;
@@ -68,35 +216,56 @@ impl From<Config> for TokenStream2 {
                 const REQUIRED: ::core::primitive::usize = #required;
                 const OPTIONAL: ::core::primitive::usize = #optional;
                 const REST: ::core::primitive::bool = #rest;
+                const KEYWORDS: &[&::core::primitive::str] = &[#(#keywords),*];
 
                 const NAME: &::core::ffi::CStr = #guile_ident;
+                const MODULE: &::core::ffi::CStr = #module;
+                const DOC: ::core::option::Option<&::core::primitive::str> = #doc;
                 const DRIVER: crate::guile::sys::scm_t_subr = {
                     assert!(Self::REQUIRED <= ::core::ffi::c_int::MAX as usize, "array lengths must be less than `i32::MAX`");
                     assert!(Self::OPTIONAL <= ::core::ffi::c_int::MAX as usize, "array lengths must be less than `i32::MAX`");
 
+                    #call_lint
+                    fn call(
+                        api: &mut crate::guile::Api,
+                        #(#required_args: crate::guile::sys::SCM,)*
+                        #(#optional_args: crate::guile::sys::SCM,)*
+                        #(#rest_arg: crate::guile::sys::SCM),*
+                    ) -> ::core::result::Result<crate::guile::Scm, crate::guile::error::GuileError> {
+                        #call
+                        crate::guile::convert::IntoScmResult::into_scm_result(output, api)
+                    }
+
                     extern "C" fn driver(
                         #(#required_args: crate::guile::sys::SCM,)*
                         #(#optional_args: crate::guile::sys::SCM,)*
                         #(#rest_arg: crate::guile::sys::SCM),*
                     ) -> crate::guile::Scm {
-                        let mut api = unsafe { crate::guile::Api::new_unchecked() };
-                        #fn_ident(
-                            &mut api,
-                            [#(crate::guile::Scm::new(#required_args)),*],
-                            [#({
-                                if #optional_args == unsafe { crate::guile::sys::REEXPORTS_SCM_UNDEFINED } {
-                                    ::core::option::Option::None
-                                } else {
-                                    ::core::option::Option::Some(crate::guile::Scm::new(#optional_args))
-                                }
-                            }),*],
-                            #(crate::guile::Scm::new(#rest_arg),)*
-                        )
+                        // Unwinding into libguile's frames would abort, so panics are thrown as scheme exceptions instead.
+                        let output = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                            call(
+                                &mut unsafe { crate::guile::Api::new_unchecked() },
+                                #(#required_args,)*
+                                #(#optional_args,)*
+                                #(#rest_arg),*
+                            )
+                        }))
+                        .unwrap_or_else(|payload| ::core::result::Result::Err(crate::guile::error::GuileError::from_panic(#guile_ident, payload)));
+
+                        // Every rust value has been dropped once `call` returns, so raising cannot skip destructors.
+                        match output {
+                            ::core::result::Result::Ok(output) => output,
+                            ::core::result::Result::Err(error) => unsafe { crate::guile::Api::new_unchecked().raise(error) },
+                        }
                     }
 
                     driver as crate::guile::sys::scm_t_subr
                 };
             }
+
+            ::inventory::submit! {
+                crate::guile::registry::Binding::new::<#struct_ident>(&[#(#param_names),*])
+            }
         }
     }
 }
@@ -105,6 +274,7 @@ impl From<Config> for TokenStream2 {
 struct ConfigBuilder {
     struct_ident: Option<String>,
     guile_ident: Option<String>,
+    module: Option<String>,
 }
 impl ConfigBuilder {
     pub fn build(
@@ -112,13 +282,18 @@ impl ConfigBuilder {
         vis: Visibility,
         fn_ident: Ident,
         inputs: Inputs,
+        doc: Option<String>,
     ) -> Result<Config, NulError> {
         CString::new(
             self.guile_ident
                 .map(|ident| ident.to_string())
                 .unwrap_or_else(|| RenameRule::KebabCase.apply_to_field(fn_ident.to_string())),
         )
-        .map(|guile_ident| Config {
+        .and_then(|guile_ident| {
+            CString::new(self.module.unwrap_or_else(|| DEFAULT_MODULE.to_string()))
+                .map(|module| (guile_ident, module))
+        })
+        .map(|(guile_ident, module)| Config {
             struct_ident: self
                 .struct_ident
                 .map(|ident| Ident::new(&ident, ident.span()))
@@ -129,6 +304,8 @@ impl ConfigBuilder {
                     )
                 }),
             guile_ident,
+            module,
+            doc,
             inputs,
             fn_ident,
             vis,
@@ -159,10 +336,12 @@ impl Parse for ConfigBuilder {
                     &mut accum.struct_ident
                 } else if path.is_ident("guile_ident") {
                     &mut accum.guile_ident
+                } else if path.is_ident("module") {
+                    &mut accum.module
                 } else {
                     return Err(syn::Error::new(
                         path.get_ident().map(|ident| ident.span()).unwrap_or_else(Span::call_site),
-                        format!("Unknown argument `{}`. Available arguments are: `struct_ident`, and `guile_ident`.", path.get_ident().map(<_>::to_string).unwrap_or_else(|| "<??>".to_string()))
+                        format!("Unknown argument `{}`. Available arguments are: `struct_ident`, `guile_ident`, and `module`.", path.get_ident().map(<_>::to_string).unwrap_or_else(|| "<??>".to_string()))
                     ));
                 };
                 *ident = Some(value.value());
@@ -202,31 +381,34 @@ where
             mutability: Some(_),
             elem,
             ..
-        }) => inner(&elem),
+        }) => inner(elem),
         _ => false,
     }
 }
-fn is_option_scm(ty: &Type) -> bool {
+/// Get the type argument of a type such as `Option<T>` if its last path segment is `ident`.
+fn generic_argument<'a>(ty: &'a Type, ident: &str) -> Option<&'a Type> {
     match ty {
         Type::Path(TypePath {
             qself: None,
             path: Path { segments, .. },
         }) => segments
             .last()
-            .map(|PathSegment { ident, arguments }| {
-                Ident::new("Option", Span::call_site()).eq(ident)
-                    && matches!(
-                        arguments,
-                        PathArguments::AngleBracketed(AngleBracketedGenericArguments {
-                            args,
-                            ..
-                        }) if args.first().map(|arg| matches!(arg, GenericArgument::Type(ty) if is_scm(ty))).unwrap_or_default()
-                    )
-            })
-            .unwrap_or_default(),
-        _ => false,
+            .filter(|PathSegment { ident: segment, .. }| segment == ident)
+            .and_then(|PathSegment { arguments, .. }| match arguments {
+                PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) => {
+                    match args.first() {
+                        Some(GenericArgument::Type(ty)) if args.len() == 1 => Some(ty),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }),
+        _ => None,
     }
 }
+fn is_option_scm(ty: &Type) -> bool {
+    generic_argument(ty, "Option").is_some_and(is_scm)
+}
 /// Return the length expression if the type is an array that passes `predicate`
 fn is_array<F>(ty: Type, predicate: F) -> Option<Expr>
 where
@@ -249,37 +431,121 @@ fn expr_to_usize(expr: Expr) -> Result<usize, syn::Error> {
     })
 }
 
+/// Whether a natively typed parameter is required, optional, or collects the rest list.
 #[derive(Clone, Copy, Debug, PartialEq)]
+enum ParamKind {
+    Required,
+    /// Parameters of type `Option<T>`.
+    Optional,
+    /// A trailing parameter of type `Rest<T>`.
+    Rest,
+    /// Parameters marked with `#[key]` or `#[key(default = EXPR)]`, which are parsed from the rest list.
+    Keyword,
+}
+impl From<&Type> for ParamKind {
+    fn from(ty: &Type) -> Self {
+        if generic_argument(ty, "Option").is_some() {
+            Self::Optional
+        } else if generic_argument(ty, "Rest").is_some() {
+            Self::Rest
+        } else {
+            Self::Required
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TypedParam {
+    /// The name used in error messages.
+    name: String,
+    ty: Box<Type>,
+    kind: ParamKind,
+    /// The value of a keyword parameter that was not passed, which is `Default::default()` if unset.
+    default: Option<Expr>,
+}
+impl TryFrom<FnArg> for TypedParam {
+    type Error = syn::Error;
+
+    fn try_from(arg: FnArg) -> Result<Self, Self::Error> {
+        match arg {
+            FnArg::Receiver(receiver) => Err(syn::Error::new(
+                receiver.span(),
+                "guile functions cannot take `self`",
+            )),
+            FnArg::Typed(PatType { attrs, pat, ty, .. }) => attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("key"))
+                .try_fold(None, |_, attr| match &attr.meta {
+                    Meta::Path(_) => Ok(Some(None)),
+                    Meta::List(_) => attr.parse_args::<MetaNameValue>().and_then(
+                        |MetaNameValue { path, value, .. }| {
+                            if path.is_ident("default") {
+                                Ok(Some(Some(value)))
+                            } else {
+                                Err(syn::Error::new(
+                                    path.span(),
+                                    "the only available argument is `default`",
+                                ))
+                            }
+                        },
+                    ),
+                    Meta::NameValue(meta) => Err(syn::Error::new(
+                        meta.span(),
+                        "expected `#[key]` or `#[key(default = EXPR)]`",
+                    )),
+                })
+                .map(|key| Self {
+                    name: match pat.as_ref() {
+                        Pat::Ident(PatIdent { ident, .. }) => ident.to_string(),
+                        // Patterns such as `Rest(flags)`
+                        Pat::TupleStruct(PatTupleStruct { elems, .. })
+                            if let Some(Pat::Ident(PatIdent { ident, .. })) = elems.first()
+                                && elems.len() == 1 =>
+                        {
+                            ident.to_string()
+                        }
+                        pat => quote!(#pat).to_string(),
+                    },
+                    kind: match key {
+                        Some(_) => ParamKind::Keyword,
+                        None => ParamKind::from(ty.as_ref()),
+                    },
+                    default: key.flatten(),
+                    ty,
+                }),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Params {
+    /// `[Scm; LEN]`, `[Option<Scm>; LEN]` and an optional trailing `Scm` for the rest list.
+    Arrays,
+    /// Parameters of any type implementing `FromScm`, or references to foreign objects, that are converted before the call.
+    Typed(Vec<TypedParam>),
+}
+
+#[derive(Debug)]
 struct Inputs {
     required: usize,
     optional: usize,
     rest: bool,
+    params: Params,
 }
-impl TryFrom<Punctuated<FnArg, Token![,]>> for Inputs {
-    type Error = syn::Error;
-
-    fn try_from(args: Punctuated<FnArg, Token![,]>) -> Result<Self, Self::Error> {
-        let args_span = args.span();
-        let mut args = args.into_iter().map(get_type);
+impl Inputs {
+    fn try_from_arrays<I>(mut args: I, args_span: Span) -> Result<Self, syn::Error>
+    where
+        I: Iterator<Item = Box<Type>>,
+    {
+        const ERROR: &str = "the second argument must be of type `[Scm; LEN]`";
         args.next()
             .map_or_else(
-                || Ok(()),
-                |arg| {
-                    is_ref_mut(&arg, is_api).then_some(()).ok_or_else(|| {
-                        syn::Error::new(arg.span(), "the first argument must be of type `&mut Api`")
-                    })
+                || Err(syn::Error::new(args_span, ERROR)),
+                |ty| {
+                    let ty_span = ty.span();
+                    is_array(*ty, is_scm).ok_or_else(|| syn::Error::new(ty_span, ERROR))
                 },
             )
-            .and_then(|_| {
-                const ERROR: &str = "the second argument must be of type `[Scm; LEN]`";
-                args.next().map_or_else(
-                    || Err(syn::Error::new(args_span, ERROR)),
-                    |ty| {
-                        let ty_span = ty.span();
-                        is_array(*ty, is_scm).ok_or_else(|| syn::Error::new(ty_span, ERROR))
-                    },
-                )
-            })
             .and_then(expr_to_usize)
             .and_then(|required| {
                 const ERROR: &str = "the third argument must be of type `[Option<Scm>; LEN]`";
@@ -312,9 +578,127 @@ impl TryFrom<Punctuated<FnArg, Token![,]>> for Inputs {
                         required,
                         optional,
                         rest,
+                        params: Params::Arrays,
                     })
             })
     }
+
+    fn try_from_typed<I>(args: I) -> Result<Self, syn::Error>
+    where
+        I: Iterator<Item = FnArg>,
+    {
+        args.map(TypedParam::try_from)
+            .try_fold(
+                (0, 0, false, Vec::new()),
+                |(mut required, mut optional, mut rest, mut params), param| {
+                    let param = param?;
+                    if params.last().is_some_and(|TypedParam { kind, .. }| *kind == ParamKind::Rest) {
+                        return Err(syn::Error::new(
+                            param.ty.span(),
+                            "the `Rest` parameter must be the last parameter",
+                        ));
+                    }
+
+                    let keywords = params
+                        .iter()
+                        .any(|TypedParam { kind, .. }| *kind == ParamKind::Keyword);
+                    match param.kind {
+                        ParamKind::Required | ParamKind::Optional | ParamKind::Rest
+                            if keywords =>
+                        {
+                            return Err(syn::Error::new(
+                                param.ty.span(),
+                                "keyword parameters must be the last parameters, and cannot be combined with `Rest`",
+                            ));
+                        }
+                        // Guile would bind the first keyword to the optional parameter.
+                        ParamKind::Keyword if optional != 0 => {
+                            return Err(syn::Error::new(
+                                param.ty.span(),
+                                "keyword parameters cannot be combined with optional parameters",
+                            ));
+                        }
+                        ParamKind::Required if optional != 0 => {
+                            return Err(syn::Error::new(
+                                param.ty.span(),
+                                "required parameters must come before optional parameters",
+                            ));
+                        }
+                        ParamKind::Required => required += 1,
+                        ParamKind::Optional => optional += 1,
+                        // Keyword arguments are passed in the rest list.
+                        ParamKind::Rest | ParamKind::Keyword => rest = true,
+                    }
+                    params.push(param);
+
+                    Ok((required, optional, rest, params))
+                },
+            )
+            .map(|(required, optional, rest, params)| Inputs {
+                required,
+                optional,
+                rest,
+                params: Params::Typed(params),
+            })
+    }
+}
+impl TryFrom<Punctuated<FnArg, Token![,]>> for Inputs {
+    type Error = syn::Error;
+
+    fn try_from(args: Punctuated<FnArg, Token![,]>) -> Result<Self, Self::Error> {
+        let args_span = args.span();
+        let mut args = args.into_iter().peekable();
+        args.next()
+            .map(get_type)
+            .map_or_else(
+                || Ok(()),
+                |arg| {
+                    is_ref_mut(&arg, is_api).then_some(()).ok_or_else(|| {
+                        syn::Error::new(arg.span(), "the first argument must be of type `&mut Api`")
+                    })
+                },
+            )
+            .and_then(|_| {
+                // Functions taking `[Scm; LEN]` receive the raw arguments instead of converted ones.
+                if args
+                    .peek()
+                    .map(|arg| {
+                        matches!(
+                            arg,
+                            FnArg::Typed(PatType { ty, .. })
+                                if matches!(ty.as_ref(), Type::Array(TypeArray { elem, .. }) if is_scm(elem))
+                        )
+                    })
+                    .unwrap_or_default()
+                {
+                    Self::try_from_arrays(args.map(get_type), args_span)
+                } else {
+                    Self::try_from_typed(args)
+                }
+            })
+    }
+}
+
+/// Join the lines of `///` comments, which are stored as `#[doc = "..."]` attributes.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue {
+                path,
+                value:
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(line),
+                        ..
+                    }),
+                ..
+            }) if path.is_ident("doc") => Some(line.value()),
+            _ => None,
+        })
+        .map(|line| line.strip_prefix(' ').map(str::to_string).unwrap_or(line))
+        .collect::<Vec<_>>();
+
+    Some(lines.join("\n").trim().to_string()).filter(|doc| !doc.is_empty())
 }
 
 fn assert_none<T>(option: Option<T>, token: &str) -> Result<(), syn::Error>
@@ -332,24 +716,37 @@ where
 
 #[proc_macro_attribute]
 pub fn guile_fn(config: TokenStream, input: TokenStream) -> TokenStream {
-    syn::parse::<ItemFn>(input.clone())
+    syn::parse::<ItemFn>(input)
+        .map(|item| {
+            // `#[key]` is only read by this macro, so it would be an unknown attribute on the emitted function.
+            let mut output = item.clone();
+            output.sig.inputs.iter_mut().for_each(|arg| {
+                if let FnArg::Typed(PatType { attrs, .. }) = arg {
+                    attrs.retain(|attr| !attr.path().is_ident("key"));
+                }
+            });
+            (item, output)
+        })
         .and_then(
-            |ItemFn {
-                 vis,
-                 sig:
-                     Signature {
-                         constness,
-                         asyncness,
-                         unsafety,
-                         variadic,
-                         generics,
-                         ident: fn_ident,
-                         inputs,
-                         output,
-                         ..
-                     },
-                 ..
-             }| {
+            |(
+                ItemFn {
+                    attrs,
+                    vis,
+                    sig:
+                        Signature {
+                            constness,
+                            asyncness,
+                            unsafety,
+                            variadic,
+                            generics,
+                            ident: fn_ident,
+                            inputs,
+                            ..
+                        },
+                    ..
+                },
+                output,
+            )| {
                 assert_none(constness, "const")
                     .and_then(|_| assert_none(asyncness, "async"))
                     .and_then(|_| assert_none(unsafety, "unsafe"))
@@ -360,24 +757,22 @@ pub fn guile_fn(config: TokenStream, input: TokenStream) -> TokenStream {
                             "generic",
                         )
                     })
-                    .and_then(|_| match output {
-                        ReturnType::Type(_, ty) if is_scm(&ty) => Ok(()),
-                        _ => Err(syn::Error::new(output.span(), "return type must be `Scm`")),
-                    })
                     .and_then(|_| syn::parse::<ConfigBuilder>(config))
                     .and_then(|builder| Inputs::try_from(inputs).map(|inputs| (builder, inputs)))
                     .and_then(|(builder, inputs)| {
                         let fn_ident_span = fn_ident.span();
-                        builder.build(vis, fn_ident, inputs).map_err(|error| {
-                            syn::Error::new(
-                                fn_ident_span,
-                                format!("identifiers cannot have nul bytes: {error}"),
-                            )
-                        })
+                        builder
+                            .build(vis, fn_ident, inputs, doc_comment(&attrs))
+                            .map_err(|error| {
+                                syn::Error::new(
+                                    fn_ident_span,
+                                    format!("identifiers cannot have nul bytes: {error}"),
+                                )
+                            })
                     })
                     .map(TokenStream2::from)
                     .map(|mut tokens| {
-                        tokens.extend([input].map(TokenStream2::from));
+                        tokens.extend(quote!(#output));
                         tokens
                     })
             },
@@ -385,3 +780,150 @@ pub fn guile_fn(config: TokenStream, input: TokenStream) -> TokenStream {
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
+
+#[derive(Default)]
+struct ForeignObjectConfig {
+    name: Option<String>,
+    module: Option<String>,
+}
+impl Parse for ForeignObjectConfig {
+    fn parse(input: ParseStream) -> Result<Self, syn::Error> {
+        Punctuated::<MetaNameValue, Token![,]>::parse_terminated(input).and_then(|items| {
+            items
+                .into_iter()
+                .try_fold(Self::default(), |mut accum, value| {
+                    let MetaNameValue {
+                        path,
+                        value:
+                            Expr::Lit(ExprLit {
+                                lit: Lit::Str(value),
+                                ..
+                            }),
+                        ..
+                    } = value
+                    else {
+                        return Err(syn::Error::new(
+                            value.span(),
+                            "arguments may only be string literals",
+                        ));
+                    };
+
+                    let field = if path.is_ident("name") {
+                        &mut accum.name
+                    } else if path.is_ident("module") {
+                        &mut accum.module
+                    } else {
+                        return Err(syn::Error::new(
+                            path.get_ident()
+                                .map(|ident| ident.span())
+                                .unwrap_or_else(Span::call_site),
+                            format!(
+                                "Unknown argument `{}`. Available arguments are: `name`, and `module`.",
+                                path.get_ident()
+                                    .map(<_>::to_string)
+                                    .unwrap_or_else(|| "<??>".to_string())
+                            ),
+                        ));
+                    };
+                    *field = Some(value.value());
+
+                    Ok(accum)
+                })
+        })
+    }
+}
+
+#[proc_macro_derive(ForeignObject, attributes(foreign_object))]
+pub fn derive_foreign_object(input: TokenStream) -> TokenStream {
+    syn::parse::<Item>(input)
+        .and_then(|item| match item {
+            Item::Struct(ItemStruct {
+                attrs,
+                ident,
+                generics,
+                ..
+            })
+            | Item::Enum(ItemEnum {
+                attrs,
+                ident,
+                generics,
+                ..
+            }) => Ok((attrs, ident, generics)),
+            item => Err(syn::Error::new(
+                item.span(),
+                "only structs and enums can be foreign objects",
+            )),
+        })
+        .and_then(|(attrs, ident, generics)| {
+            if generics != Default::default() {
+                return Err(syn::Error::new(
+                    generics.span(),
+                    "foreign objects cannot be generic",
+                ));
+            }
+
+            attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("foreign_object"))
+                .try_fold(ForeignObjectConfig::default(), |accum, attr| {
+                    attr.parse_args::<ForeignObjectConfig>()
+                        .map(|config| ForeignObjectConfig {
+                            name: config.name.or(accum.name),
+                            module: config.module.or(accum.module),
+                        })
+                })
+                .and_then(|config| {
+                    CString::new(
+                        config
+                            .name
+                            .unwrap_or_else(|| RenameRule::KebabCase.apply_to_variant(ident.to_string())),
+                    )
+                    .and_then(|name| {
+                        CString::new(config.module.unwrap_or_else(|| DEFAULT_MODULE.to_string()))
+                            .map(|module| (name, module))
+                    })
+                    .map_err(|error| {
+                        syn::Error::new(
+                            ident.span(),
+                            format!("names cannot have nul bytes: {error}"),
+                        )
+                    })
+                })
+                .map(|(name, module)| {
+                    quote! {
+                        impl crate::guile::foreign::ForeignObject for #ident {
+                            const NAME: &'static ::core::ffi::CStr = #name;
+                            const MODULE: &'static ::core::ffi::CStr = #module;
+
+                            fn foreign_type() -> &'static crate::guile::foreign::ForeignType {
+                                static FOREIGN_TYPE: crate::guile::foreign::ForeignType = crate::guile::foreign::ForeignType::new();
+                                &FOREIGN_TYPE
+                            }
+                        }
+
+                        impl crate::guile::convert::IntoScm for #ident {
+                            fn into_scm(self, api: &crate::guile::Api) -> crate::guile::Scm {
+                                api.make_foreign(self)
+                            }
+                        }
+                    }
+                })
+        })
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::Inputs, syn::FnArg};
+
+    #[test]
+    fn optional_and_keyword() {
+        let args: [FnArg; 2] = [
+            syn::parse_quote!(from: Option<f64>),
+            syn::parse_quote!(#[key] fade: f64),
+        ];
+        let error = Inputs::try_from_typed(args.into_iter()).err().unwrap();
+        assert!(error.to_string().contains("optional parameters"));
+    }
+}
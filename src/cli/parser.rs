@@ -20,9 +20,14 @@
 
 use {
     crate::{
-        config::{default_paths::DEFAULT_PATHS, path_segments::choice::Choice},
+        config::{
+            default_paths::{DEFAULT_CACHE_DIRS, DEFAULT_PATHS, DEFAULT_PLUGIN_DIRS},
+            path_segments::choice::Choice,
+        },
         display::IntoDisplay,
+        guile::registry::{self, Format},
     },
+    bstr::BStr,
     const_format::{formatc, formatcp},
     getargs::{Opt, Options},
     std::{
@@ -31,6 +36,8 @@ use {
         fmt::{self, Display, Formatter},
         io::{self, Write},
         path::Path,
+        str,
+        time::Duration,
     },
 };
 
@@ -46,6 +53,16 @@ pub struct Config<'a> {
     /// The path to the entry point for the configuration file.
     config_file: Option<&'a Path>,
     exprs: Vec<&'a [u8]>,
+    /// Start a REPL after loading the configuration file.
+    repl: bool,
+    /// Serve REPLs on this socket after loading the configuration file.
+    repl_socket: Option<&'a Path>,
+    /// Check the configuration file for errors instead of running.
+    check: bool,
+    /// Skip defining the scheme modules bundled with empl.
+    no_prelude: bool,
+    /// How long loading the configuration file may take before it is interrupted.
+    timeout: Option<Duration>,
 }
 impl<'a> Config<'a> {
     /// Parser some cli flags.
@@ -73,14 +90,32 @@ impl<'a> Config<'a> {
                                     "Usage: {} [OPTIONS..]
 
 Options:
-  -h --help           Print this message and exit.
-  -v --version        Print version information and exit.
-  -c --config  [PATH] Set the path to the entrypoint to the config file.
-                      Defaults to {}.
-  -e --eval    [EXPR] Add an expression that will be evaluated at the end
-                      of the config file.\n",
+  -h --help                Print this message and exit.
+  -v --version             Print version information and exit.
+  -c --config      [PATH]  Set the path to the entrypoint to the config file.
+                           Defaults to {}.
+  -e --eval        [EXPR]  Add an expression that will be evaluated at the end
+                           of the config file.
+  -r --repl                Start a REPL with every empl module imported after
+                           loading the config file.
+     --repl-socket [PATH]  Serve REPLs on a unix socket after loading the
+                           config file. Only the current user can connect.
+     --timeout   [SECONDS] Interrupt loading the config file or reloading it
+                           once it has run for longer than SECONDS.
+     --check               Evaluate the config file without affecting the
+                           player, report every error with its location, and
+                           exit.
+     --no-prelude          Do not define the scheme modules bundled with empl,
+                           such as `(empl prelude)`.
+     --dump-api  [FORMAT]  Print a reference for every scheme binding and exit.
+                           FORMAT is one of `markdown`, `texinfo`, or `json`.
+
+Compiled config files are cached in {}.
+Plugins loaded with `(use-plugin 'NAME)` are searched for in {}.\n",
                                     env!("CARGO_BIN_NAME"),
                                     Choice::new(DEFAULT_PATHS).unwrap(),
+                                    Choice::new(DEFAULT_CACHE_DIRS).unwrap(),
+                                    Choice::new(DEFAULT_PLUGIN_DIRS).unwrap(),
                                 )
                                 .as_bytes()
                             },
@@ -113,12 +148,83 @@ Options:
                 Opt::Short(b'e') | Opt::Long(b"eval") => {
                     output.exprs.push(opts.value()?);
                 }
+                Opt::Short(b'r') | Opt::Long(b"repl") => {
+                    output.repl = true;
+                }
+                Opt::Long(b"timeout") => {
+                    let seconds = opts.value()?;
+                    output.timeout = Some(
+                        str::from_utf8(seconds)
+                            .ok()
+                            .and_then(|seconds| seconds.parse().ok())
+                            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                            .filter(|timeout| !timeout.is_zero())
+                            .ok_or(ParseCliArgumentsError::InvalidTimeout(seconds))?,
+                    );
+                }
+                Opt::Long(b"check") => {
+                    output.check = true;
+                }
+                Opt::Long(b"no-prelude") => {
+                    output.no_prelude = true;
+                }
+                Opt::Long(b"repl-socket") => {
+                    output.repl_socket = Some(Path::new(unsafe {
+                        OsStr::from_encoded_bytes_unchecked(opts.value()?)
+                    }));
+                }
+                Opt::Long(b"dump-api") => {
+                    let format = opts.value()?;
+                    return Format::from_name(format)
+                        .ok_or(ParseCliArgumentsError::UnknownApiFormat(format))
+                        .and_then(|format| {
+                            registry::write(format, &registry::bindings(), stdout)
+                                .and_then(|_| stdout.flush())
+                                .map(|_| None)
+                                .map_err(ParseCliArgumentsError::PrintStdout)
+                        });
+                }
                 flag => return Err(ParseCliArgumentsError::UnknownFlag(flag)),
             }
         }
 
         Ok(Some(output))
     }
+
+    /// The path to the entry point for the configuration file if it was set explicitly.
+    pub const fn config_file(&self) -> Option<&'a Path> {
+        self.config_file
+    }
+
+    /// Expressions to evaluate after the configuration file in the order they were given.
+    pub fn exprs(&self) -> &[&'a [u8]] {
+        &self.exprs
+    }
+
+    /// Whether to start a REPL after loading the configuration file.
+    pub const fn repl(&self) -> bool {
+        self.repl
+    }
+
+    /// The socket to serve REPLs on if one was requested.
+    pub const fn repl_socket(&self) -> Option<&'a Path> {
+        self.repl_socket
+    }
+
+    /// Whether to check the configuration file for errors instead of running.
+    pub const fn check(&self) -> bool {
+        self.check
+    }
+
+    /// Whether to skip defining the scheme modules bundled with empl.
+    pub const fn no_prelude(&self) -> bool {
+        self.no_prelude
+    }
+
+    /// How long loading the configuration file may take, if it is limited.
+    pub const fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 }
 
 #[derive(Debug)]
@@ -127,6 +233,8 @@ pub enum ParseCliArgumentsError<'a> {
     MissingValue(Opt<&'a [u8]>),
     UnexpectedValue(Opt<&'a [u8]>),
     UnknownFlag(Opt<&'a [u8]>),
+    UnknownApiFormat(&'a [u8]),
+    InvalidTimeout(&'a [u8]),
 }
 impl Display for ParseCliArgumentsError<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
@@ -137,6 +245,16 @@ impl Display for ParseCliArgumentsError<'_> {
                 write!(f, "flag `{}` does not take a value", flag.display())
             }
             Self::UnknownFlag(flag) => write!(f, "unexpected flag `{}`", flag.display()),
+            Self::UnknownApiFormat(format) => write!(
+                f,
+                "unknown api format `{}`, expected one of `markdown`, `texinfo`, or `json`",
+                BStr::new(format)
+            ),
+            Self::InvalidTimeout(seconds) => write!(
+                f,
+                "invalid timeout `{}`, expected a positive number of seconds",
+                BStr::new(seconds)
+            ),
         }
     }
 }
@@ -165,14 +283,22 @@ mod tests {
 
     #[test]
     fn cli_required_args() {
-        [b"-c" as &[u8], b"--config", b"-e", b"--eval"]
-            .into_iter()
-            .for_each(|arg| {
-                assert!(matches!(
-                    Config::new(iter::once(arg), &mut io::empty()).unwrap_err(),
-                    ParseCliArgumentsError::MissingValue(_)
-                ))
-            })
+        [
+            b"-c" as &[u8],
+            b"--config",
+            b"-e",
+            b"--eval",
+            b"--dump-api",
+            b"--repl-socket",
+            b"--timeout",
+        ]
+        .into_iter()
+        .for_each(|arg| {
+            assert!(matches!(
+                Config::new(iter::once(arg), &mut io::empty()).unwrap_err(),
+                ParseCliArgumentsError::MissingValue(_)
+            ))
+        })
     }
 
     #[test]
@@ -182,6 +308,46 @@ mod tests {
             (&[b"--help"], None),
             (&[b"-v"], None),
             (&[b"--version"], None),
+            (&[b"--dump-api", b"json"], None),
+            (
+                &[b"-r"],
+                Some(Config {
+                    repl: true,
+                    ..Default::default()
+                }),
+            ),
+            (
+                &[b"--repl", b"-cfoo"],
+                Some(Config {
+                    config_file: Some(Path::new("foo")),
+                    repl: true,
+                    ..Default::default()
+                }),
+            ),
+            (
+                &[b"-r", b"--repl-socket", b"/tmp/repl.sock"],
+                Some(Config {
+                    repl: true,
+                    repl_socket: Some(Path::new("/tmp/repl.sock")),
+                    ..Default::default()
+                }),
+            ),
+            (
+                &[b"--check", b"-e(foo)"],
+                Some(Config {
+                    exprs: vec![b"(foo)"],
+                    check: true,
+                    ..Default::default()
+                }),
+            ),
+            (
+                &[b"--no-prelude"],
+                Some(Config {
+                    no_prelude: true,
+                    ..Default::default()
+                }),
+            ),
+            (&[b"--dump-api=markdown"], None),
             (
                 &[b"-cfoo"],
                 Some(Config {
@@ -213,16 +379,51 @@ mod tests {
         });
     }
 
+    #[test]
+    fn timeouts() {
+        assert_eq!(
+            Config::new([b"--timeout=1.5" as &[u8]], &mut io::empty())
+                .unwrap()
+                .unwrap()
+                .timeout(),
+            Some(Duration::from_millis(1500))
+        );
+        [b"0" as &[u8], b"-1", b"soon", b"inf"]
+            .into_iter()
+            .for_each(|seconds| {
+                assert!(matches!(
+                    Config::new([b"--timeout" as &[u8], seconds], &mut io::empty()).unwrap_err(),
+                    ParseCliArgumentsError::InvalidTimeout(_)
+                ))
+            });
+    }
+
+    #[test]
+    fn unknown_api_format() {
+        assert!(matches!(
+            Config::new([b"--dump-api" as &[u8], b"html"], &mut io::empty()).unwrap_err(),
+            ParseCliArgumentsError::UnknownApiFormat(b"html")
+        ));
+    }
+
     #[test]
     fn stdout_ends_in_newline() {
         let mut stdout = Vec::new();
 
-        [b"-h" as &[u8], b"--help", b"-v", b"--version"]
-            .into_iter()
-            .for_each(|flag| {
-                stdout.clear();
-                Config::new(iter::once(flag), &mut stdout).unwrap();
-                assert_eq!(*stdout.last().unwrap(), b'\n');
-            })
+        [
+            &[b"-h" as &[u8]] as &[&[u8]],
+            &[b"--help"],
+            &[b"-v"],
+            &[b"--version"],
+            &[b"--dump-api", b"markdown"],
+            &[b"--dump-api", b"texinfo"],
+            &[b"--dump-api", b"json"],
+        ]
+        .into_iter()
+        .for_each(|args| {
+            stdout.clear();
+            Config::new(args.iter().copied(), &mut stdout).unwrap();
+            assert_eq!(*stdout.last().unwrap(), b'\n');
+        })
     }
 }
@@ -16,6 +16,11 @@
 // You should have received a copy of the GNU General Public License
 // along with empl.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod cache;
+pub mod check;
 pub mod default_paths;
+pub mod entrypoint;
 pub mod path_segment;
 pub mod path_segments;
+pub mod plugins;
+pub mod reload;
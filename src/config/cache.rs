@@ -0,0 +1,387 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Compiled config files, which are cached in [DEFAULT_CACHE_DIRS] so they are not interpreted on every launch.
+//!
+//! Every compiled file has a stamp next to it listing the modification time and hash of each file it was compiled from.
+//! A compiled file is reused as long as every file in its stamp still has the same hash, which is only computed again when the modification time has changed.
+
+use {
+    crate::{
+        config::default_paths::DEFAULT_CACHE_DIRS,
+        guile::{
+            Api, Scm, convert::FromScm, convert::IntoScm, error::GuileError,
+            protected::ProtectedScm,
+        },
+    },
+    std::{
+        ffi::{CStr, OsStr},
+        fs, io,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+        time::UNIX_EPOCH,
+    },
+};
+
+/// Compile `source` into `output` in the current module, returning every file that was read during compilation.
+///
+/// Included files are passed to `%load-hook` when they are expanded, which is how they are found.
+const COMPILE_SOURCE: &CStr = c"
+(lambda (source output)
+  (let ((files (list source))
+        (load-hook (@ (guile) %load-hook)))
+    (dynamic-wind
+      (lambda ()
+        (set! (@ (guile) %load-hook)
+              (lambda (file)
+                (set! files (cons file files))
+                (when load-hook (load-hook file)))))
+      (lambda ()
+        ((@ (system base compile) compile-file) source
+         #:output-file output
+         #:env (current-module)))
+      (lambda () (set! (@ (guile) %load-hook) load-hook)))
+    (reverse files)))";
+
+/// Pass every file to `%load-hook`, since loading compiled code does not.
+const ANNOUNCE_SOURCE: &CStr = c"
+(lambda (files)
+  (let ((load-hook (@ (guile) %load-hook)))
+    (when load-hook (for-each load-hook files))))";
+
+static COMPILE: OnceLock<ProtectedScm> = OnceLock::new();
+static ANNOUNCE: OnceLock<ProtectedScm> = OnceLock::new();
+static LOAD_THUNK: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// 64 bit FNV-1a, which is stable across releases unlike [std::hash::DefaultHasher].
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A file that a compiled file was compiled from.
+#[derive(Clone, Debug, PartialEq)]
+struct Dependency {
+    path: PathBuf,
+    /// Nanoseconds since the unix epoch.
+    modified: u128,
+    hash: u64,
+}
+impl Dependency {
+    fn modified(path: &Path) -> io::Result<u128> {
+        fs::metadata(path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|modified| modified.as_nanos())
+            .map_err(io::Error::other)
+    }
+
+    fn new(path: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            modified: Self::modified(&path)?,
+            hash: hash(&fs::read(&path)?),
+            path,
+        })
+    }
+
+    /// The dependency with its current modification time if the file still has the same contents, or [None] if it changed.
+    ///
+    /// The contents are only hashed when the modification time cannot be trusted.
+    /// That is when it changed, or when it is not older than the stamp written at `stamped`, since a write in the same tick of a coarse clock keeps the same time.
+    fn refresh(&self, stamped: u128) -> Option<Self> {
+        let modified = Self::modified(&self.path).ok()?;
+        if modified == self.modified && modified < stamped {
+            return Some(self.clone());
+        }
+
+        (hash(&fs::read(&self.path).ok()?) == self.hash).then(|| Self {
+            modified,
+            ..self.clone()
+        })
+    }
+
+    /// `MODIFIED HASH PATH` on a single line.
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(format!("{} {:016x} ", self.modified, self.hash).as_bytes());
+        output.extend_from_slice(self.path.as_os_str().as_bytes());
+        output.push(b'\n');
+    }
+
+    fn parse(line: &[u8]) -> Option<Self> {
+        let mut fields = line.splitn(3, |&byte| byte == b' ');
+        let mut field = || fields.next().and_then(|field| str::from_utf8(field).ok());
+        let modified = field()?.parse().ok()?;
+        let hash = u64::from_str_radix(field()?, 16).ok()?;
+        let path = fields.next().filter(|path| !path.is_empty())?;
+
+        Some(Self {
+            path: PathBuf::from(OsStr::from_bytes(path)),
+            modified,
+            hash,
+        })
+    }
+}
+
+/// Find the first cache directory that can be resolved.
+///
+/// # Safety
+///
+/// See [PathSegment::to_path][crate::config::path_segment::PathSegment::to_path]'s section on safety.
+pub unsafe fn resolve_dir() -> Option<PathBuf> {
+    DEFAULT_CACHE_DIRS
+        .iter()
+        // SAFETY: the preconditions are thrown into this function
+        .find_map(|path| unsafe { path.to_path_buf() }.ok())
+}
+
+/// The compiled file and stamp for `source` in `dir`, named after a hash of its canonical path.
+fn cache_paths(dir: &Path, source: &Path) -> (PathBuf, PathBuf) {
+    let canonical = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+    let name = format!("{:016x}", hash(canonical.as_os_str().as_encoded_bytes()));
+    (
+        dir.join(format!("{name}.go")),
+        dir.join(format!("{name}.stamp")),
+    )
+}
+
+/// The dependencies in `stamp` if every one of them is fresh.
+///
+/// The stamp is written again when a dependency had to be hashed, so its modification time can be trusted next time.
+fn fresh_dependencies(stamp: &Path) -> Option<Vec<Dependency>> {
+    let stamped = Dependency::modified(stamp).ok()?;
+    let dependencies = fs::read(stamp)
+        .ok()?
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(Dependency::parse)
+        .collect::<Option<Vec<_>>>()
+        .filter(|dependencies| !dependencies.is_empty())?;
+
+    let refreshed = dependencies
+        .iter()
+        .map(|dependency| dependency.refresh(stamped))
+        .collect::<Option<Vec<_>>>()?;
+    if refreshed != dependencies
+        || refreshed
+            .iter()
+            .any(|dependency| dependency.modified >= stamped)
+    {
+        // Hashing again next time is the worst case.
+        let _ = write_stamp(stamp, &refreshed);
+    }
+
+    Some(refreshed)
+}
+
+/// Write the stamp through a temporary file so it is never seen half written.
+fn write_stamp(stamp: &Path, dependencies: &[Dependency]) -> io::Result<()> {
+    let mut contents = Vec::new();
+    dependencies
+        .iter()
+        .for_each(|dependency| dependency.write(&mut contents));
+
+    let temporary = stamp.with_extension("stamp.tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(temporary, stamp)
+}
+
+/// Compile `source` into `compiled`, returning the files it was compiled from.
+fn compile(api: &mut Api, source: &Path, compiled: &Path, stamp: &Path) -> Option<Vec<PathBuf>> {
+    fs::create_dir_all(compiled.parent()?).ok()?;
+
+    let args = [source.into_scm(api), compiled.into_scm(api)];
+    let files = api
+        .eval_once(&COMPILE, COMPILE_SOURCE)
+        .call(api, &args)
+        .and_then(|files| Vec::<PathBuf>::from_scm(api, files))
+        .ok()?;
+
+    // Without a stamp the compiled file is never used, so compiling again next time is the worst case.
+    let dependencies = files
+        .iter()
+        .cloned()
+        .map(Dependency::new)
+        .collect::<io::Result<Vec<_>>>()
+        .ok()?;
+    write_stamp(stamp, &dependencies).ok()?;
+
+    Some(files)
+}
+
+/// Load `source` in the current module, compiling it into `cache_dir` or reusing an earlier compilation when possible.
+///
+/// `c_source` must be the same path as `source`.
+/// Files are interpreted like [Api::load] when there is no cache directory, or when they cannot be compiled.
+///
+/// # Errors
+///
+/// Fails if evaluating the file throws an exception.
+pub fn load(
+    api: &mut Api,
+    source: &Path,
+    c_source: &CStr,
+    cache_dir: Option<&Path>,
+) -> Result<Scm, GuileError> {
+    let Some(cache_dir) = cache_dir else {
+        return api.try_load(c_source);
+    };
+    let (compiled, stamp) = cache_paths(cache_dir, source);
+
+    let files = match fresh_dependencies(&stamp) {
+        Some(dependencies) if compiled.is_file() => dependencies
+            .into_iter()
+            .map(|dependency| dependency.path)
+            .collect(),
+        _ => match compile(api, source, &compiled, &stamp) {
+            Some(files) => files,
+            None => return api.try_load(c_source),
+        },
+    };
+
+    let compiled = compiled.into_scm(api);
+    let Ok(thunk) = api
+        .eval_once(&LOAD_THUNK, c"(@ (system vm loader) load-thunk-from-file)")
+        .call(api, &[compiled])
+    else {
+        // The compiled file is unusable, such as when it came from a different version of guile.
+        let _ = fs::remove_file(&stamp);
+        return api.try_load(c_source);
+    };
+
+    let files = files.into_scm(api);
+    api.eval_once(&ANNOUNCE, ANNOUNCE_SOURCE)
+        .call(api, &[files])?;
+    thunk.call(api, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile,
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK, temp_path},
+        },
+        std::{
+            fs::File,
+            time::{Duration, UNIX_EPOCH},
+        },
+    };
+
+    #[test]
+    fn fnv() {
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn stamp_lines() {
+        let dependency = Dependency {
+            path: PathBuf::from("/config/with spaces.scm"),
+            modified: 1_700_000_000_123_456_789,
+            hash: 0xdead_beef,
+        };
+        let mut line = Vec::new();
+        dependency.write(&mut line);
+
+        assert_eq!(line.pop(), Some(b'\n'));
+        assert_eq!(Dependency::parse(&line), Some(dependency));
+        assert_eq!(Dependency::parse(b"12 zz /foo.scm"), None);
+        assert_eq!(Dependency::parse(b"12 ff "), None);
+        assert_eq!(Dependency::parse(b"12 ff"), None);
+        assert_eq!(Dependency::parse(b"ff /foo.scm"), None);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn compiled_once() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let dir = temp_path("cache");
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.scm");
+        fs::write(&source, "(define cached-value (+ 1 2))").unwrap();
+        let c_source = std::ffi::CString::new(source.as_os_str().as_encoded_bytes()).unwrap();
+        let (compiled, stamp) = cache_paths(&cache_dir, &source);
+
+        guile::with_guile(|api| {
+            load(api, &source, &c_source, Some(&cache_dir)).unwrap();
+            assert!(compiled.is_file());
+            let dependencies = fresh_dependencies(&stamp).unwrap();
+            assert_eq!(dependencies.len(), 1);
+            assert_eq!(dependencies[0].path, source);
+
+            // A fresh stamp means the compiled file is loaded without compiling again, which would reset its modification time.
+            let marked = UNIX_EPOCH + Duration::from_secs(1);
+            File::options()
+                .write(true)
+                .open(&compiled)
+                .unwrap()
+                .set_modified(marked)
+                .unwrap();
+            load(api, &source, &c_source, Some(&cache_dir)).unwrap();
+            assert_eq!(fs::metadata(&compiled).unwrap().modified().unwrap(), marked);
+            assert_eq!(
+                i32::from_scm(api, api.eval_cstring(c"cached-value")).unwrap(),
+                3
+            );
+
+            // Files whose modification time has not changed since the stamp was written are not hashed again.
+            let set_modified = |seconds| {
+                File::options()
+                    .write(true)
+                    .open(&source)
+                    .unwrap()
+                    .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+                    .unwrap()
+            };
+            set_modified(2);
+            let unhashed = Dependency {
+                path: source.clone(),
+                modified: Duration::from_secs(2).as_nanos(),
+                hash: 0,
+            };
+            write_stamp(&stamp, &[unhashed]).unwrap();
+            assert!(fresh_dependencies(&stamp).is_some());
+            set_modified(3);
+            assert!(fresh_dependencies(&stamp).is_none());
+
+            fs::write(&source, "(define cached-value 4)").unwrap();
+            assert!(fresh_dependencies(&stamp).is_none());
+            load(api, &source, &c_source, Some(&cache_dir)).unwrap();
+            assert_eq!(
+                i32::from_scm(api, api.eval_cstring(c"cached-value")).unwrap(),
+                4
+            );
+
+            // A cache directory that cannot be created falls back to interpreting.
+            fs::write(&source, "(define cached-value 5)").unwrap();
+            load(api, &source, &c_source, Some(&source.join("cache"))).unwrap();
+            assert_eq!(
+                i32::from_scm(api, api.eval_cstring(c"cached-value")).unwrap(),
+                5
+            );
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
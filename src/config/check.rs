@@ -0,0 +1,410 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Dry runs of the configuration for `--check`, which report every error along with where it happened.
+
+use {
+    crate::{
+        cli::parser::Config,
+        config::entrypoint::{self, LoadConfigError},
+        guile::{
+            Api, Scm, convert::FromScm, convert::IntoScm, error::GuileError,
+            protected::ProtectedScm,
+        },
+    },
+    parking_lot::Mutex,
+    std::{
+        ffi::CStr,
+        fmt::{self, Display, Formatter},
+        path::{Path, PathBuf},
+        str,
+        sync::OnceLock,
+    },
+};
+
+/// Read the next form from `port`, returning it along with its zero based line and column, or `#f` at the end of the file.
+///
+/// Forms without source properties, such as symbols, use the position of the port after reading instead.
+const READ_FORM_SOURCE: &CStr = c"
+(lambda (port)
+  (let ((form (read port)))
+    (and (not (eof-object? form))
+         (let ((source (or (source-properties form) '())))
+           (cons form
+                 (if (assq-ref source 'line)
+                     (cons (assq-ref source 'line) (assq-ref source 'column))
+                     (cons (port-line port) (port-column port))))))))";
+
+const PORT_POSITION_SOURCE: &CStr = c"(lambda (port) (cons (port-line port) (port-column port)))";
+
+static READ_FORM: OnceLock<ProtectedScm> = OnceLock::new();
+static PORT_POSITION: OnceLock<ProtectedScm> = OnceLock::new();
+static OPEN_INPUT_FILE: OnceLock<ProtectedScm> = OnceLock::new();
+static PRIMITIVE_EVAL: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// Set while a configuration is being checked.
+static DRY_RUN: Mutex<Option<DryRun>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct DryRun {
+    /// The form that is currently being evaluated.
+    origin: Origin,
+    effects: Vec<Diagnostic>,
+}
+
+/// Record `effect` instead of performing it if a configuration is being checked.
+///
+/// Bindings that affect the player should return early when this returns true.
+pub fn record_effect<F>(effect: F) -> bool
+where
+    F: FnOnce() -> String,
+{
+    match DRY_RUN.lock().as_mut() {
+        Some(dry_run) => {
+            let origin = dry_run.origin.clone();
+            dry_run.effects.push(Diagnostic {
+                origin,
+                message: effect(),
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Where a form came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    /// A position in a file, where lines start from 1 and columns start from 0 like guile's own messages.
+    File {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    /// An expression passed with `-e`.
+    Expr(String),
+}
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::File { path, line, column } => write!(f, "{}:{line}:{column}", path.display()),
+            Self::Expr(expr) => write!(f, "expression `{expr}`"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub origin: Origin,
+    pub message: String,
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}: {}", self.origin, self.message)
+    }
+}
+
+/// The outcome of checking a configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    /// Every exception that was thrown.
+    pub errors: Vec<Diagnostic>,
+    /// Everything that bindings would have done to the player.
+    pub effects: Vec<Diagnostic>,
+}
+
+fn set_origin(origin: Origin) {
+    if let Some(dry_run) = DRY_RUN.lock().as_mut() {
+        dry_run.origin = origin;
+    }
+}
+
+/// Add `error` to `errors`, returning it again if the check cannot continue because its time ran out or it was interrupted.
+fn push_error(
+    errors: &mut Vec<Diagnostic>,
+    origin: Origin,
+    error: GuileError,
+) -> Result<(), GuileError> {
+    errors.push(Diagnostic {
+        origin,
+        message: error.to_string(),
+    });
+
+    match error.key() {
+        "timeout" | "interrupted" => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Call `evaluate` with `origin` as the origin of the effects it records.
+///
+/// `add-hook!` is part of guile rather than a binding, so procedures added to the hooks in `(empl hooks)` are recorded as effects here.
+fn evaluate<F>(api: &mut Api, origin: Origin, evaluate: F) -> Result<Scm, GuileError>
+where
+    F: FnOnce(&mut Api) -> Result<Scm, GuileError>,
+{
+    set_origin(origin);
+    let before = api.hook_sizes();
+    let output = evaluate(api);
+
+    api.hook_sizes()
+        .into_iter()
+        .zip(before)
+        .for_each(|((hook, after), (_, before))| {
+            (before..after).for_each(|_| {
+                record_effect(|| format!("add a procedure to `{}`", hook.to_string_lossy()));
+            })
+        });
+
+    output
+}
+
+/// Evaluate every top level form in `path` separately, so an error does not hide the ones after it.
+///
+/// # Errors
+///
+/// Fails if the check was interrupted, after the interruption was added to `errors`.
+fn check_file(api: &mut Api, path: &Path, errors: &mut Vec<Diagnostic>) -> Result<(), GuileError> {
+    let origin = |(line, column): (usize, usize)| Origin::File {
+        path: path.to_path_buf(),
+        line: line + 1,
+        column,
+    };
+
+    let file = path.into_scm(api);
+    let port = match api
+        .eval_once(&OPEN_INPUT_FILE, c"open-input-file")
+        .call(api, &[file])
+    {
+        Ok(port) => port,
+        Err(error) => return push_error(errors, origin((0, 0)), error),
+    };
+
+    loop {
+        let form = api
+            .eval_once(&READ_FORM, READ_FORM_SOURCE)
+            .call(api, &[port])
+            .and_then(|form| Option::<(Scm, (usize, usize))>::from_scm(api, form));
+
+        match form {
+            Ok(None) => return Ok(()),
+            Ok(Some((form, position))) => {
+                if let Err(error) = evaluate(api, origin(position), |api| {
+                    api.eval_once(&PRIMITIVE_EVAL, c"primitive-eval")
+                        .call(api, &[form])
+                }) {
+                    push_error(errors, origin(position), error)?;
+                }
+            }
+            // The reader cannot continue after invalid syntax, so report it where it stopped.
+            Err(error) => {
+                let position = api
+                    .eval_once(&PORT_POSITION, PORT_POSITION_SOURCE)
+                    .call(api, &[port])
+                    .and_then(|position| <(usize, usize)>::from_scm(api, position))
+                    .unwrap_or_default();
+                return push_error(errors, origin(position), error);
+            }
+        }
+    }
+}
+
+/// Evaluate an expression passed with `-e`.
+///
+/// # Errors
+///
+/// See [check_file].
+fn check_expr(api: &mut Api, expr: &[u8], errors: &mut Vec<Diagnostic>) -> Result<(), GuileError> {
+    let origin = Origin::Expr(String::from_utf8_lossy(expr).into_owned());
+    let expr = match str::from_utf8(expr) {
+        Ok(expr) => expr,
+        Err(error) => {
+            errors.push(Diagnostic {
+                origin,
+                message: error.to_string(),
+            });
+            return Ok(());
+        }
+    };
+
+    match evaluate(api, origin.clone(), |api| {
+        let expr = api.make_string(expr);
+        api.try_eval_string(expr)
+    }) {
+        Ok(_) => Ok(()),
+        Err(error) => push_error(errors, origin, error),
+    }
+}
+
+/// Evaluate the configuration entrypoint and every expression passed with `-e`, while bindings that affect the player only record what they would have done.
+///
+/// Evaluation stops once the timeout set with `--timeout` has passed, which is reported like any other error.
+///
+/// # Errors
+///
+/// Fails if the entrypoint could not be found or read, or if [entrypoint::prepare] fails. Exceptions are collected into the [Report] instead.
+///
+/// # Safety
+///
+/// See [entrypoint::resolve]'s section on safety.
+pub unsafe fn run<'a>(api: &mut Api, config: &Config<'a>) -> Result<Report, LoadConfigError<'a>> {
+    let (path, _) = unsafe { entrypoint::open(config) }?;
+
+    /// Ends the dry run even if the check returns early, so bindings never keep only recording effects.
+    struct EndDryRun;
+    impl Drop for EndDryRun {
+        fn drop(&mut self) {
+            DRY_RUN.lock().take();
+        }
+    }
+
+    let _end_dry_run = EndDryRun;
+    *DRY_RUN.lock() = Some(DryRun {
+        origin: Origin::File {
+            path: path.to_path_buf(),
+            line: 1,
+            column: 0,
+        },
+        effects: Vec::new(),
+    });
+    // SAFETY: the preconditions are thrown into this function
+    unsafe { entrypoint::prepare(api, config) }?;
+
+    let mut errors = Vec::new();
+    // An interrupted check has already been added to the errors.
+    let _ = api.with_deadline(config.timeout(), |api| {
+        check_file(api, &path, &mut errors)?;
+        config
+            .exprs()
+            .iter()
+            .try_for_each(|expr| check_expr(api, expr, &mut errors))
+    });
+
+    let effects = DRY_RUN
+        .lock()
+        .take()
+        .map(|dry_run| dry_run.effects)
+        .unwrap_or_default();
+    Ok(Report { errors, effects })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile,
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK, temp_path},
+        },
+        std::{fs, io},
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn report() {
+        // The dry run is shared with every other test.
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        let path = temp_path("check.scm");
+        fs::write(
+            &path,
+            "(define x 1)
+  (car x)
+(use-modules (empl config) (empl hooks) (empl timers))
+(reload-config)
+(run-after 5 (lambda () #t))
+(add-hook! paused-hook (lambda () #t))
+(display \"unterminated",
+        )
+        .unwrap();
+        let config = Config::new(
+            [
+                b"-c" as &[u8],
+                path.as_os_str().as_encoded_bytes(),
+                b"-e(cdr x)",
+                b"-e(set! x 2)",
+            ],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let report = guile::with_guile(|api| unsafe { run(api, &config) }).unwrap();
+        let origins = report
+            .errors
+            .iter()
+            .map(|error| error.origin.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(origins.len(), 3);
+        assert_eq!(origins[0], format!("{}:2:2", path.display()));
+        // The unterminated string is reported wherever the reader gave up on line 7.
+        assert!(origins[1].starts_with(&format!("{}:7:", path.display())));
+        assert_eq!(origins[2], "expression `(cdr x)`");
+        assert_eq!(
+            report
+                .effects
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                format!("{}:4:0: reload the config", path.display()),
+                format!("{}:5:0: call a thunk after 5 seconds", path.display()),
+                format!("{}:6:0: add a procedure to `paused-hook`", path.display()),
+            ]
+        );
+
+        // Effects are performed again once the check is over.
+        assert!(!record_effect(String::new));
+
+        guile::with_guile(|api| api.eval_cstring(c"(reset-hook! paused-hook)"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn timeout() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        let path = temp_path("check-timeout.scm");
+        fs::write(&path, "(let loop () (loop))\n(car 1)").unwrap();
+        let config = Config::new(
+            [
+                b"-c" as &[u8],
+                path.as_os_str().as_encoded_bytes(),
+                b"--timeout=0.1",
+                b"-e(cdr 1)",
+            ],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let report = guile::with_guile(|api| unsafe { run(api, &config) }).unwrap();
+        // Nothing after the form that timed out is evaluated.
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(
+            report.errors[0].origin.to_string(),
+            format!("{}:1:0", path.display())
+        );
+        assert!(report.errors[0].message.contains("took longer than"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
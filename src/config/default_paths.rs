@@ -29,7 +29,28 @@ cfg_if! {
             PathSegment::Segment("config"),
             PathSegment::Segment("main.scm"),
         ])];
+
+        /// Where compiled config files are cached.
+        pub const DEFAULT_CACHE_DIRS: &[PathSegments] = &[PathSegments::new(&[
+            PathSegment::EnvVar(c"%LOCALAPPDATA%"),
+            PathSegment::Segment("empl"),
+            PathSegment::Segment("cache"),
+        ])];
+
+        /// Where plugins are searched for, in order.
+        pub const DEFAULT_PLUGIN_DIRS: &[PathSegments] = &[PathSegments::new(&[
+            PathSegment::EnvVar(c"%APPDATA%"),
+            PathSegment::Segment("empl"),
+            PathSegment::Segment("plugins"),
+        ])];
     } else if #[cfg(target_os = "macos")] {
+        /// The socket that `(start-repl-server)` listens on when no path is given.
+        pub const DEFAULT_REPL_SOCKET: PathSegments = PathSegments::new(&[
+            PathSegment::EnvVar(c"TMPDIR"),
+            PathSegment::Segment("empl"),
+            PathSegment::Segment("repl.sock"),
+        ]);
+
         pub const DEFAULT_PATHS: &[PathSegments] = &[PathSegments::new(&[
             PathSegment::HomeDir,
             PathSegment::Segment("Library"),
@@ -37,7 +58,31 @@ cfg_if! {
             PathSegment::Segment("empl"),
             PathSegment::Segment("main.scm"),
         ])];
+
+        /// Where compiled config files are cached.
+        pub const DEFAULT_CACHE_DIRS: &[PathSegments] = &[PathSegments::new(&[
+            PathSegment::HomeDir,
+            PathSegment::Segment("Library"),
+            PathSegment::Segment("Caches"),
+            PathSegment::Segment("empl"),
+        ])];
+
+        /// Where plugins are searched for, in order.
+        pub const DEFAULT_PLUGIN_DIRS: &[PathSegments] = &[PathSegments::new(&[
+            PathSegment::HomeDir,
+            PathSegment::Segment("Library"),
+            PathSegment::Segment("Application Support"),
+            PathSegment::Segment("empl"),
+            PathSegment::Segment("plugins"),
+        ])];
     } else if #[cfg(unix)] {
+        /// The socket that `(start-repl-server)` listens on when no path is given.
+        pub const DEFAULT_REPL_SOCKET: PathSegments = PathSegments::new(&[
+            PathSegment::EnvVar(c"XDG_RUNTIME_DIR"),
+            PathSegment::Segment("empl"),
+            PathSegment::Segment("repl.sock"),
+        ]);
+
         pub const DEFAULT_PATHS: &[PathSegments] = &[
             PathSegments::new(&[
                 PathSegment::EnvVar(c"XDG_CONFIG_HOME"),
@@ -51,6 +96,33 @@ cfg_if! {
                 PathSegment::Segment("main.scm"),
             ]),
         ];
+
+        /// Where compiled config files are cached.
+        pub const DEFAULT_CACHE_DIRS: &[PathSegments] = &[
+            PathSegments::new(&[
+                PathSegment::EnvVar(c"XDG_CACHE_HOME"),
+                PathSegment::Segment("empl"),
+            ]),
+            PathSegments::new(&[
+                PathSegment::HomeDir,
+                PathSegment::Segment(".cache"),
+                PathSegment::Segment("empl"),
+            ]),
+        ];
+
+        /// Where plugins are searched for, in order.
+        pub const DEFAULT_PLUGIN_DIRS: &[PathSegments] = &[
+            PathSegments::new(&[
+                PathSegment::EnvVar(c"XDG_DATA_HOME"),
+                PathSegment::Segment("empl"),
+                PathSegment::Segment("plugins"),
+            ]),
+            PathSegments::new(&[
+                PathSegment::EnvVar(c"XDG_CONFIG_HOME"),
+                PathSegment::Segment("empl"),
+                PathSegment::Segment("plugins"),
+            ]),
+        ];
     } else {
         compile_error!("unsupported platform");
     }
@@ -0,0 +1,367 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Loading of the configuration entrypoint and the expressions passed on the command line.
+
+use {
+    crate::{
+        cli::parser::Config,
+        config::{
+            cache,
+            default_paths::DEFAULT_PATHS,
+            path_segments::choice::Choice,
+            plugins,
+            reload::{self, LoadedConfig},
+        },
+        guile::{Api, error::GuileError},
+        prelude,
+        timers::Timer,
+    },
+    bstr::BStr,
+    const_format::formatc,
+    std::{
+        borrow::Cow,
+        error::Error,
+        ffi::CString,
+        fmt::{self, Display, Formatter},
+        fs::File,
+        io,
+        path::Path,
+        str::{self, Utf8Error},
+    },
+};
+
+/// Find the path to the configuration entrypoint.
+///
+/// A path set with `-c` is always preferred over the first resolvable entry of [DEFAULT_PATHS].
+///
+/// # Safety
+///
+/// See [PathSegment::to_path][crate::config::path_segment::PathSegment::to_path]'s section on safety.
+pub unsafe fn resolve<'a>(config: &Config<'a>) -> Result<Cow<'a, Path>, LoadConfigError<'a>> {
+    match config.config_file() {
+        Some(path) => Ok(Cow::Borrowed(path)),
+        None => DEFAULT_PATHS
+            .iter()
+            // SAFETY: the preconditions are thrown into this function
+            .find_map(|path| unsafe { path.to_path_buf() }.ok())
+            .map(Cow::Owned)
+            .ok_or(LoadConfigError::UnresolvedPath),
+    }
+}
+
+/// Find the path to the configuration entrypoint and make sure that it can be read.
+///
+/// # Safety
+///
+/// See [resolve]'s section on safety.
+pub unsafe fn open<'a>(
+    config: &Config<'a>,
+) -> Result<(Cow<'a, Path>, CString), LoadConfigError<'a>> {
+    let path = unsafe { resolve(config) }?;
+    if let Err(error) = File::open(&path) {
+        return Err(match error.kind() {
+            io::ErrorKind::NotFound => LoadConfigError::MissingFile(path),
+            _ => LoadConfigError::UnreadableFile(path, error),
+        });
+    }
+    let c_path = match CString::new(path.as_os_str().as_encoded_bytes()) {
+        Ok(c_path) => c_path,
+        Err(error) => {
+            return Err(LoadConfigError::UnreadableFile(
+                path,
+                io::Error::new(io::ErrorKind::InvalidInput, error),
+            ));
+        }
+    };
+
+    Ok((path, c_path))
+}
+
+/// Define every registered binding, hook, and foreign type, then put the plugin directories on the load path and load the prelude unless `--no-prelude` was passed.
+///
+/// This is everything that [load] and [check::run][crate::config::check::run] do before evaluating the config.
+///
+/// # Safety
+///
+/// See [resolve]'s section on safety.
+pub unsafe fn prepare<'a>(api: &mut Api, config: &Config<'a>) -> Result<(), LoadConfigError<'a>> {
+    // SAFETY: the preconditions are thrown into this function
+    let plugin_dirs = unsafe { plugins::resolve_dirs() };
+
+    api.define_bindings();
+    api.define_hooks();
+    api.define_foreign_type::<Timer>();
+    plugins::add_to_load_path(api, plugin_dirs)
+        .and_then(|_| {
+            if config.no_prelude() {
+                Ok(())
+            } else {
+                prelude::load(api)
+            }
+        })
+        .map_err(LoadConfigError::Setup)
+}
+
+/// Define every registered binding and hook, and load the configuration entrypoint, then evaluate every expression passed with `-e` in order.
+///
+/// The entrypoint is compiled and cached by [cache::load], and loading is interrupted once the timeout set with `--timeout` has passed.
+///
+/// The configuration is remembered so that [reload::reload] can load it again.
+///
+/// # Safety
+///
+/// See [resolve]'s section on safety.
+pub unsafe fn load<'a>(api: &mut Api, config: &Config<'a>) -> Result<(), LoadConfigError<'a>> {
+    let (path, c_path) = unsafe { open(config) }?;
+    // SAFETY: the preconditions are thrown into this function
+    let cache_dir = unsafe { cache::resolve_dir() };
+    // SAFETY: see above
+    unsafe { prepare(api, config) }?;
+    let (exprs, files) = api
+        .with_deadline(config.timeout(), |api| {
+            reload::current_module(api).and_then(|module| {
+                reload::track_files(api, module, |api| {
+                    cache::load(api, &path, &c_path, cache_dir.as_deref())
+                        .map_err(|error| LoadConfigError::EvalFile(path.clone(), error))?;
+
+                    config
+                        .exprs()
+                        .iter()
+                        .map(|expr| {
+                            str::from_utf8(expr)
+                                .map_err(|error| LoadConfigError::NonUtf8Expr(expr, error))
+                                .and_then(|expr| {
+                                    let expr_string = api.make_string(expr);
+                                    api.try_eval_string(expr_string)
+                                        .map(|_| expr.to_owned())
+                                        .map_err(|error| LoadConfigError::EvalExpr(expr, error))
+                                })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+        })
+        .map_err(|error| LoadConfigError::EvalFile(path.clone(), error))?;
+
+    reload::remember(LoadedConfig {
+        entrypoint: path.into_owned(),
+        cache_dir,
+        timeout: config.timeout(),
+        exprs: exprs?,
+        files,
+    });
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum LoadConfigError<'a> {
+    /// None of the [DEFAULT_PATHS] could be resolved.
+    UnresolvedPath,
+    MissingFile(Cow<'a, Path>),
+    UnreadableFile(Cow<'a, Path>, io::Error),
+    EvalFile(Cow<'a, Path>, GuileError),
+    /// The plugin directories could not be added to the load path, or the prelude failed to load.
+    Setup(GuileError),
+    NonUtf8Expr(&'a [u8], Utf8Error),
+    EvalExpr(&'a str, GuileError),
+}
+impl Display for LoadConfigError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::UnresolvedPath => f.write_str(formatc!(
+                "failed to find a path for the config file, tried {}",
+                Choice::new(DEFAULT_PATHS).unwrap(),
+            )),
+            Self::MissingFile(path) => {
+                write!(f, "config file `{}` does not exist", path.display())
+            }
+            Self::UnreadableFile(path, error) => {
+                write!(
+                    f,
+                    "failed to read config file `{}`: {error}",
+                    path.display()
+                )
+            }
+            Self::EvalFile(path, error) => {
+                write!(
+                    f,
+                    "failed to evaluate config file `{}`: {error}",
+                    path.display()
+                )
+            }
+            Self::Setup(error) => {
+                write!(
+                    f,
+                    "failed to set up the plugin directories and the prelude: {error}"
+                )
+            }
+            Self::NonUtf8Expr(expr, error) => {
+                write!(
+                    f,
+                    "expression `{}` is not valid utf8: {error}",
+                    BStr::new(expr)
+                )
+            }
+            Self::EvalExpr(expr, error) => {
+                write!(f, "failed to evaluate expression `{expr}`: {error}")
+            }
+        }
+    }
+}
+impl Error for LoadConfigError<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile,
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK, temp_path},
+        },
+        std::{fs, io, path::PathBuf},
+    };
+
+    fn temp_config(name: &str, contents: &str) -> PathBuf {
+        let path = temp_path(&format!("{name}.scm"));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn explicit_path() {
+        let config = Config::new([b"-cfoo.scm" as &[u8]], &mut io::empty())
+            .unwrap()
+            .unwrap();
+        assert_eq!(unsafe { resolve(&config) }.unwrap(), Path::new("foo.scm"));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn missing_file() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let config = Config::new(
+            [b"-c" as &[u8], b"/this/path/does/not/exist.scm"],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            assert!(matches!(
+                unsafe { load(api, &config) },
+                Err(LoadConfigError::MissingFile(_))
+            ));
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn eval_order() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let path = temp_config("eval-order", "(define eval-order '(file))");
+        let config = Config::new(
+            [
+                b"-c" as &[u8],
+                path.as_os_str().as_encoded_bytes(),
+                b"-e(set! eval-order (cons 'foo eval-order))",
+                b"-e(set! eval-order (cons 'bar eval-order))",
+            ],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            unsafe { load(api, &config) }.unwrap();
+            assert!(
+                api.eval_cstring(c"(equal? eval-order '(bar foo file))")
+                    .is_true()
+            );
+        });
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn timeout() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let path = temp_config("timeout", "(let loop () (loop))");
+        let config = Config::new(
+            [
+                b"--timeout=0.05" as &[u8],
+                b"-c",
+                path.as_os_str().as_encoded_bytes(),
+            ],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            assert!(matches!(
+                unsafe { load(api, &config) },
+                Err(LoadConfigError::EvalFile(_, error)) if error.key() == "timeout"
+            ));
+        });
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn eval_errors() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let path = temp_config("eval-errors", "(car 1)");
+        let config = Config::new(
+            [b"-c" as &[u8], path.as_os_str().as_encoded_bytes()],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            assert!(matches!(
+                unsafe { load(api, &config) },
+                Err(LoadConfigError::EvalFile(..))
+            ));
+        });
+
+        fs::write(&path, "").unwrap();
+        let config = Config::new(
+            [
+                b"-c" as &[u8],
+                path.as_os_str().as_encoded_bytes(),
+                b"-e(car 1)",
+            ],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            assert!(matches!(
+                unsafe { load(api, &config) },
+                Err(LoadConfigError::EvalExpr("(car 1)", _))
+            ));
+        });
+        fs::remove_file(path).unwrap();
+    }
+}
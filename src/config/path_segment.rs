@@ -183,6 +183,16 @@ impl PartialEq for GetPathSegmentError<'_> {
         }
     }
 }
+impl Display for GetPathSegmentError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            #[cfg(unix)]
+            Self::ReadPwd(error) => write!(f, "failed to read the password database: {error}"),
+            Self::UnknownEnvVar(error) => error.fmt(f),
+        }
+    }
+}
+impl Error for GetPathSegmentError<'_> {}
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,298 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Plugins, which are guile modules found in the plugin directories and loaded with `(use-plugin 'name)`.
+//!
+//! Every plugin declares the version of empl it was written for by defining `empl-version` as a string at the top level, such as `(define empl-version "3.1")`.
+//! A plugin is only loaded if this version of empl is compatible with it, following the rules of semantic versioning.
+
+use {
+    crate::{
+        config::{check, default_paths::DEFAULT_PLUGIN_DIRS},
+        guile::{
+            Api, Scm,
+            convert::{FromScm, IntoScm},
+            error::GuileError,
+            guile_fn,
+            protected::ProtectedScm,
+        },
+    },
+    parking_lot::Mutex,
+    std::{ffi::CStr, path::PathBuf, sync::OnceLock},
+};
+
+/// Puts `dirs` at the front of `%load-path`, moving them there if they were already in it.
+const PREPEND_LOAD_PATH_SOURCE: &CStr = c"
+(lambda (dirs)
+  (set! %load-path
+        (append dirs (filter (lambda (dir) (not (member dir dirs))) %load-path))))";
+
+/// Finds the file of the module named by a symbol or a list of symbols, and reads its `empl-version` without evaluating it.
+///
+/// Returns `(path file . empl-version)`, where `path` is the file name of the module without an extension.
+/// `file` and `empl-version` are `#f` if they were not found.
+const RESOLVE_PLUGIN_SOURCE: &CStr = c"
+(lambda (name)
+  (define (version-form? form)
+    (and (list? form)
+         (= (length form) 3)
+         (eq? (car form) 'define)
+         (eq? (cadr form) 'empl-version)
+         (string? (caddr form))))
+  (let* ((path (string-join (map symbol->string (if (symbol? name) (list name) name)) \"/\"))
+         (file (%search-load-path path)))
+    (cons path
+          (cons file
+                (and file
+                     (call-with-input-file file
+                       (lambda (port)
+                         (let loop ((form (read port)))
+                           (cond ((eof-object? form) #f)
+                                 ((version-form? form) (caddr form))
+                                 (else (loop (read port))))))))))))";
+
+/// Loads a plugin and imports its public interface into the current module.
+const IMPORT_PLUGIN_SOURCE: &CStr = c"
+(lambda (name)
+  (module-use! (current-module)
+               (resolve-interface (if (symbol? name) (list name) name))))";
+
+static PREPEND_LOAD_PATH: OnceLock<ProtectedScm> = OnceLock::new();
+static RESOLVE_PLUGIN: OnceLock<ProtectedScm> = OnceLock::new();
+static IMPORT_PLUGIN: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// The directories that were added to `%load-path` by [add_to_load_path].
+static PLUGIN_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The version of empl that plugins are checked against.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Every plugin directory that can be resolved, in the order they are searched.
+///
+/// # Safety
+///
+/// See [PathSegment::to_path][crate::config::path_segment::PathSegment::to_path]'s section on safety.
+pub unsafe fn resolve_dirs() -> Vec<PathBuf> {
+    DEFAULT_PLUGIN_DIRS
+        .iter()
+        // SAFETY: the preconditions are thrown into this function
+        .filter_map(|path| unsafe { path.to_path_buf() }.ok())
+        .collect()
+}
+
+/// Put `dirs` in front of guile's `%load-path`, so plugins are found before any other module.
+pub fn add_to_load_path(api: &mut Api, dirs: Vec<PathBuf>) -> Result<(), GuileError> {
+    let load_path = dirs.clone().into_scm(api);
+    api.eval_once(&PREPEND_LOAD_PATH, PREPEND_LOAD_PATH_SOURCE)
+        .call(api, &[load_path])?;
+
+    *PLUGIN_DIRS.lock() = dirs;
+    Ok(())
+}
+
+/// Parse a version such as `3`, `3.1`, or `3.1.4`, where missing numbers are zero.
+///
+/// Pre-release and build metadata such as `-rc.1` and `+build` are ignored.
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let version = version
+        .split_once(['-', '+'])
+        .map_or(version, |(version, _)| version);
+    let mut numbers = [0; 3];
+    let mut parts = version.split('.');
+    for (number, part) in numbers.iter_mut().zip(&mut parts) {
+        *number = part.parse().ok()?;
+    }
+
+    parts.next().is_none().then_some(numbers)
+}
+
+/// Whether a plugin written for `required` works with `version`.
+///
+/// Like cargo, versions before 1.0.0 are only compatible within the same minor version.
+fn is_compatible(required: [u64; 3], version: [u64; 3]) -> bool {
+    let significant = match required {
+        [0, 0, _] => 3,
+        [0, _, _] => 2,
+        _ => 1,
+    };
+
+    required[..significant] == version[..significant] && required <= version
+}
+
+/// Load the plugin named `name` and import its public bindings into the current module.
+///
+/// `name` is a symbol such as `'now-playing`, which is loaded from `now-playing.scm` in the first plugin directory that has it.
+/// A list of symbols such as `'(team status)` loads `team/status.scm` instead.
+/// The plugin must define `empl-version` as the version of empl it was written for, which is read and checked before the plugin is loaded.
+///
+/// A plugin that is found in more than one plugin directory is reported, along with the one that was loaded.
+#[guile_fn(module = "empl config")]
+fn use_plugin(api: &mut Api, name: Scm) -> Result<(), GuileError> {
+    let resolved = api
+        .eval_once(&RESOLVE_PLUGIN, RESOLVE_PLUGIN_SOURCE)
+        .call(api, &[name])?;
+    let (path, (found, required)) =
+        <(String, (Option<String>, Option<String>))>::from_scm(api, resolved)?;
+
+    let file = format!("{path}.scm");
+    let candidates = PLUGIN_DIRS
+        .lock()
+        .iter()
+        .map(|dir| dir.join(&file))
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    if let [loaded, shadowed @ ..] = candidates.as_slice()
+        && !shadowed.is_empty()
+    {
+        api.warn(&format!(
+            "plugin `{path}` is defined in more than one plugin directory, using `{}` over {}",
+            loaded.display(),
+            shadowed
+                .iter()
+                .map(|path| format!("`{}`", path.display()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if found.is_none() {
+        return Err(GuileError::new(
+            "plugin-error",
+            format!("no plugin named `{path}` was found"),
+        ));
+    }
+    let required = required.ok_or_else(|| {
+        GuileError::new(
+            "plugin-error",
+            format!("plugin `{path}` does not define `empl-version`"),
+        )
+    })?;
+    let compatible = parse_version(&required)
+        .ok_or_else(|| {
+            GuileError::new(
+                "plugin-error",
+                format!(
+                    "plugin `{path}` defines `empl-version` as `{required}`, which is not a version"
+                ),
+            )
+        })
+        .map(|required| {
+            is_compatible(
+                required,
+                parse_version(VERSION).expect("cargo versions are semantic versions"),
+            )
+        })?;
+    if !compatible {
+        return Err(GuileError::new(
+            "plugin-error",
+            format!(
+                "plugin `{path}` was written for empl {required}, which conflicts with empl {VERSION}"
+            ),
+        ));
+    }
+
+    // The plugin is still loaded while checking, so the bindings it exports can be checked too, and the effects of its own bindings are recorded.
+    check::record_effect(|| format!("load plugin `{path}` for empl {required}"));
+    api.eval_once(&IMPORT_PLUGIN, IMPORT_PLUGIN_SOURCE)
+        .call(api, &[name])
+        .map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile,
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK, temp_path},
+        },
+        std::fs,
+    };
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("3"), Some([3, 0, 0]));
+        assert_eq!(parse_version("3.1.4"), Some([3, 1, 4]));
+        assert_eq!(parse_version("3.1.0-rc.1"), Some([3, 1, 0]));
+        assert_eq!(parse_version("3.1.0+build.5"), Some([3, 1, 0]));
+        assert!(parse_version(VERSION).is_some());
+        assert_eq!(parse_version("3.1.4.1"), None);
+        assert_eq!(parse_version("3.x"), None);
+        assert_eq!(parse_version(""), None);
+
+        assert!(is_compatible([3, 0, 0], [3, 2, 0]));
+        assert!(is_compatible([3, 2, 0], [3, 2, 1]));
+        assert!(!is_compatible([3, 3, 0], [3, 2, 1]));
+        assert!(!is_compatible([2, 0, 0], [3, 0, 0]));
+        assert!(!is_compatible([0, 1, 0], [0, 2, 0]));
+        assert!(!is_compatible([0, 0, 1], [0, 0, 2]));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn use_plugin() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        let dir = temp_path("plugins");
+        fs::create_dir_all(dir.join("team")).unwrap();
+        fs::write(
+            dir.join("team/status.scm"),
+            "(define-module (team status) #:export (status))
+             (define empl-version \"3\")
+             (define (status) 'playing)",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("ancient.scm"),
+            "(define-module (ancient))
+             (define empl-version \"2.1\")
+             (error \"incompatible plugins must not be loaded\")",
+        )
+        .unwrap();
+        fs::write(dir.join("unversioned.scm"), "(define-module (unversioned))").unwrap();
+
+        guile::with_guile(|api| {
+            api.define_bindings();
+            add_to_load_path(api, vec![dir.clone()]).unwrap();
+            api.eval_cstring(c"(use-modules (empl config))");
+
+            api.eval_cstring(c"(use-plugin '(team status))");
+            assert_eq!(
+                String::from_scm(api, api.eval_cstring(c"(symbol->string (status))")).unwrap(),
+                "playing"
+            );
+
+            let error = api.try_eval_cstring(c"(use-plugin 'ancient)").unwrap_err();
+            assert_eq!(error.key(), "plugin-error");
+            assert!(error.message().contains("conflicts with empl"));
+
+            let error = api
+                .try_eval_cstring(c"(use-plugin 'unversioned)")
+                .unwrap_err();
+            assert!(error.message().contains("does not define `empl-version`"));
+
+            let error = api
+                .try_eval_cstring(c"(use-plugin 'missing-plugin)")
+                .unwrap_err();
+            assert!(error.message().contains("no plugin named `missing-plugin`"));
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
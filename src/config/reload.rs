@@ -0,0 +1,302 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Reloading the configuration from `(reload-config)`, SIGHUP, or a change to one of the files it was loaded from.
+//!
+//! A reload swaps in the hooks and timers of the new configuration only once it has loaded, with [Api::replace_hooks] and [Api::replace_timers].
+//! empl has no keybindings or themes yet, so nothing else is swapped; they will need a swap point like those when they are added.
+
+#[cfg(unix)]
+pub mod watcher;
+
+use {
+    crate::{
+        config::{cache, check},
+        guile::{
+            Api, Scm,
+            convert::{FromScm, IntoScm},
+            error::GuileError,
+            guile_fn,
+            protected::ProtectedScm,
+        },
+    },
+    parking_lot::Mutex,
+    std::{
+        ffi::{CStr, CString},
+        mem,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+        time::Duration,
+    },
+};
+
+/// Makes `include` in `module` pass every included file to `%load-hook`, which is wrapped to call `record` with every file, then switches to `module`.
+///
+/// Returns the state needed by [UNTRACK_FILES] to undo it, which includes the binding of `include` that was replaced, if any.
+const TRACK_FILES_SOURCE: &CStr = c"
+(lambda (module record)
+  (define previous-include
+    (let ((variable (module-local-variable module 'include)))
+      (and variable (variable-bound? variable) (list (variable-ref variable)))))
+  (eval '(define-syntax include
+           (lambda (form)
+             (syntax-case form ()
+               ((_ file)
+                (let* ((name (syntax->datum #'file))
+                       (source (syntax-source form))
+                       (from (and source (assq-ref source 'filename)))
+                       (path (if (or (absolute-file-name? name) (not (string? from)))
+                                 name
+                                 (in-vicinity (dirname from) name))))
+                  (let ((load-hook (@ (guile) %load-hook)))
+                    (when load-hook (load-hook path)))
+                  #`((@ (guile) include) #,(datum->syntax #'file path)))))))
+        module)
+  (let ((load-hook (@ (guile) %load-hook)))
+    (set! (@ (guile) %load-hook)
+          (lambda (file)
+            (record file)
+            (when load-hook (load-hook file))))
+    (list load-hook (set-current-module module) module previous-include)))";
+
+const UNTRACK_FILES_SOURCE: &CStr = c"
+(lambda (state)
+  (apply
+    (lambda (load-hook previous-module module previous-include)
+      (set! (@ (guile) %load-hook) load-hook)
+      (set-current-module previous-module)
+      (if previous-include
+          (module-define! module 'include (car previous-include))
+          (module-remove! module 'include)))
+    state))";
+
+static TRACK_FILES: OnceLock<ProtectedScm> = OnceLock::new();
+static UNTRACK_FILES: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// The last configuration that was loaded successfully.
+static LOADED: Mutex<Option<LoadedConfig>> = Mutex::new(None);
+
+/// Everything needed to load a configuration again.
+#[derive(Clone, Debug)]
+pub struct LoadedConfig {
+    pub entrypoint: PathBuf,
+    /// Where compiled files are cached, if anywhere.
+    pub cache_dir: Option<PathBuf>,
+    /// How long loading may take before it is interrupted.
+    pub timeout: Option<Duration>,
+    pub exprs: Vec<String>,
+    /// Every file that was loaded or included, which includes the entrypoint.
+    pub files: Vec<PathBuf>,
+}
+
+fn guile_procedure(api: &mut Api, name: &CStr) -> Result<Scm, GuileError> {
+    api.lookup_public(c"guile", name)?.ok_or_else(|| {
+        GuileError::new(
+            "unbound-variable",
+            format!("`{}` is not defined", name.to_string_lossy()),
+        )
+    })
+}
+
+/// The module that expressions are currently evaluated in.
+pub fn current_module(api: &mut Api) -> Result<Scm, GuileError> {
+    guile_procedure(api, c"current-module")?.call(api, &[])
+}
+
+/// Call `load` with `module` as the current module, returning its output along with every file that was loaded or included in the meantime.
+///
+/// `load` must not let exceptions escape, so it should only evaluate scheme through functions that catch them, such as [Api::try_load].
+pub fn track_files<F, T>(
+    api: &mut Api,
+    module: Scm,
+    load: F,
+) -> Result<(T, Vec<PathBuf>), GuileError>
+where
+    F: FnOnce(&mut Api) -> T,
+{
+    let files = Arc::new(Mutex::new(Vec::new()));
+    let record = {
+        let files = Arc::clone(&files);
+        api.make_procedure(move |api, args| {
+            let &[file] = args else {
+                return Err(GuileError::new(
+                    "wrong-number-of-args",
+                    "expected the name of a file",
+                ));
+            };
+            files.lock().push(PathBuf::from_scm(api, file)?);
+            Ok(().into_scm(api))
+        })
+    };
+
+    let state = api
+        .eval_once(&TRACK_FILES, TRACK_FILES_SOURCE)
+        .call(api, &[module, record])?;
+    let output = load(api);
+    api.eval_once(&UNTRACK_FILES, UNTRACK_FILES_SOURCE)
+        .call(api, &[state])?;
+
+    let mut files = mem::take(&mut *files.lock());
+    files.sort();
+    files.dedup();
+    Ok((output, files))
+}
+
+/// Remember a configuration that loaded successfully, so it can be reloaded later.
+pub fn remember(loaded: LoadedConfig) {
+    *LOADED.lock() = Some(loaded);
+
+    #[cfg(unix)]
+    watcher::rewatch();
+}
+
+/// Every file that the current configuration was loaded from.
+pub fn watched_files() -> Vec<PathBuf> {
+    LOADED
+        .lock()
+        .as_ref()
+        .map(|loaded| loaded.files.clone())
+        .unwrap_or_default()
+}
+
+/// Load the configuration entrypoint and the expressions passed with `-e` again in a fresh module, replacing every hook.
+///
+/// Loading is interrupted once the timeout set with `--timeout` has passed.
+///
+/// # Errors
+///
+/// Fails if no configuration has been loaded, or if loading it throws an exception.
+/// The hooks from the previous configuration are kept when this fails.
+pub fn reload(api: &mut Api) -> Result<(), GuileError> {
+    let Some(loaded) = LOADED.lock().clone() else {
+        return Err(GuileError::new(
+            "misc-error",
+            "no config has been loaded yet",
+        ));
+    };
+    let entrypoint = CString::new(loaded.entrypoint.as_os_str().as_encoded_bytes())
+        .map_err(|error| GuileError::new("misc-error", error.to_string()))?;
+
+    let module = guile_procedure(api, c"make-fresh-user-module")?.call(api, &[])?;
+    let files = api.replace_hooks(|api| {
+        api.replace_timers(|api| {
+            api.with_deadline(loaded.timeout, |api| {
+                track_files(api, module, |api| {
+                    cache::load(
+                        api,
+                        &loaded.entrypoint,
+                        &entrypoint,
+                        loaded.cache_dir.as_deref(),
+                    )?;
+                    loaded.exprs.iter().try_for_each(|expr| {
+                        let expr = api.make_string(expr);
+                        api.try_eval_string(expr).map(drop)
+                    })
+                })
+            })
+            .and_then(|(output, files)| output.map(|_| files))
+        })
+    })?;
+
+    remember(LoadedConfig { files, ..loaded });
+    Ok(())
+}
+
+/// Load the config again in a fresh module, replacing every hook.
+///
+/// The previous config stays active if loading fails, and the error is raised.
+#[guile_fn(module = "empl config")]
+fn reload_config(api: &mut Api) -> Result<(), GuileError> {
+    if check::record_effect(|| "reload the config".to_owned()) {
+        return Ok(());
+    }
+
+    reload(api)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            cli::parser::Config,
+            config::entrypoint,
+            guile,
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK, temp_path},
+        },
+        std::{fs, io},
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn reload_config() {
+        // Hooks and the loaded config are shared with every other test.
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        let dir = temp_path("reload");
+        fs::create_dir_all(&dir).unwrap();
+        let entrypoint = dir.join("init.scm");
+        let included = dir.join("included.scm");
+        fs::write(
+            &entrypoint,
+            "(use-modules (empl hooks)) (include \"included.scm\")",
+        )
+        .unwrap();
+        fs::write(&included, "(add-hook! paused-hook (lambda () 'first))").unwrap();
+
+        let config = Config::new(
+            [b"-c" as &[u8], entrypoint.as_os_str().as_encoded_bytes()],
+            &mut io::empty(),
+        )
+        .unwrap()
+        .unwrap();
+        guile::with_guile(|api| {
+            unsafe { entrypoint::load(api, &config) }.unwrap();
+            assert_eq!(watched_files(), [included.clone(), entrypoint.clone()]);
+            // The `include` that tracks files is gone once loading has finished.
+            assert!(
+                api.eval_cstring(c"(not (module-local-variable (current-module) 'include))")
+                    .is_true()
+            );
+
+            api.eval_cstring(
+                c"(define (paused-results)
+                    (map (lambda (procedure) (procedure)) (hook->list paused-hook)))",
+            );
+
+            fs::write(&included, "(car 1)").unwrap();
+            assert!(reload(api).is_err());
+            assert!(
+                api.eval_cstring(c"(equal? (paused-results) '(first))")
+                    .is_true()
+            );
+
+            fs::write(&included, "(add-hook! paused-hook (lambda () 'second))").unwrap();
+            reload(api).unwrap();
+            assert!(
+                api.eval_cstring(c"(equal? (paused-results) '(second))")
+                    .is_true()
+            );
+
+            api.eval_cstring(c"(reset-hook! paused-hook)");
+        });
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,321 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Thread that reloads the configuration on SIGHUP, or on linux, when one of its files changes.
+//!
+//! The thread never enters guile itself, it sends the reload to the [Executor] so it never runs alongside hooks or timers.
+
+use {
+    crate::guile::executor::{Executor, Priority},
+    std::{
+        io,
+        os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+        sync::atomic::{AtomicI32, Ordering},
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+#[cfg(target_os = "linux")]
+use {
+    libc::inotify_event,
+    std::{
+        collections::{BTreeSet, HashMap},
+        ffi::CString,
+        mem,
+        path::{Path, PathBuf},
+        ptr,
+    },
+};
+
+/// The write end of the pipe that wakes the watcher thread, or `-1` if it has not been spawned.
+static WAKE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Written to the pipe when SIGHUP is received.
+const WAKE_RELOAD: u8 = b'r';
+/// Written to the pipe when the files to watch have changed.
+const WAKE_REWATCH: u8 = b'w';
+/// Written to the pipe by [stop].
+const WAKE_STOP: u8 = b's';
+
+/// How long to wait after a file changes before reloading, so editors can finish writing.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// Wake the watcher thread, which is async signal safe.
+fn wake(byte: u8) {
+    let fd = WAKE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // A full pipe already has a wake up pending, so errors are ignored.
+        unsafe { libc::write(fd, (&raw const byte).cast(), 1) };
+    }
+}
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    wake(WAKE_RELOAD);
+}
+
+/// Tell the watcher thread that [super::watched_files] has changed.
+pub fn rewatch() {
+    wake(WAKE_REWATCH);
+}
+
+/// Make the watcher thread exit without reloading again.
+pub fn stop() {
+    wake(WAKE_STOP);
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// Create a pipe whose ends are closed on exec and never block.
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [-1; 2];
+    check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    // SAFETY: both ends were just opened and are not owned by anything else.
+    let [read, write] = fds.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+
+    [&read, &write].into_iter().try_for_each(|fd| {
+        check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+        check(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) }).map(drop)
+    })?;
+
+    Ok((read, write))
+}
+
+/// Read everything that is available from a non blocking `fd`.
+fn drain(fd: RawFd) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        match unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) } {
+            length @ 1.. => output.extend_from_slice(&buffer[..length as usize]),
+            _ => return output,
+        }
+    }
+}
+
+/// Spawn the watcher thread, which reloads the configuration on `executor`, and make SIGHUP reload it.
+///
+/// The thread exits, dropping `executor`, once [stop] is called.
+///
+/// # Errors
+///
+/// Fails if the thread has already been spawned, or if the signal handler or inotify could not be set up.
+pub fn spawn(executor: Executor) -> io::Result<JoinHandle<()>> {
+    let (read, write) = pipe()?;
+    let inotify = Inotify::new()?;
+
+    WAKE_FD
+        .compare_exchange(-1, write.as_raw_fd(), Ordering::AcqRel, Ordering::Relaxed)
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the watcher is already running",
+            )
+        })?;
+    // The signal handler may write to the pipe at any point from now on.
+    let _ = write.into_raw_fd();
+
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    check(unsafe { libc::sigemptyset(&raw mut action.sa_mask) })?;
+    check(unsafe { libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) })?;
+
+    Ok(thread::spawn(move || watch(read, inotify, executor)))
+}
+
+fn watch(wake: OwnedFd, mut inotify: Inotify, executor: Executor) {
+    inotify.watch(&super::watched_files());
+
+    loop {
+        let mut fds = [
+            libc::pollfd {
+                fd: wake.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                // `poll` ignores negative file descriptors.
+                fd: inotify.fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        if let Err(error) = check(unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) }) {
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            eprintln!("config watcher stopped: {error}");
+            return;
+        }
+
+        let mut reload = false;
+        let mut rewatch = false;
+        let mut stop = false;
+        if fds[0].revents & libc::POLLIN != 0 {
+            drain(wake.as_raw_fd())
+                .into_iter()
+                .for_each(|byte| match byte {
+                    WAKE_RELOAD => reload = true,
+                    WAKE_STOP => stop = true,
+                    _ => rewatch = true,
+                });
+        }
+        if stop {
+            return;
+        }
+        if fds[1].revents & libc::POLLIN != 0 {
+            thread::sleep(SETTLE);
+            reload |= inotify.changed();
+        }
+
+        // A reload that succeeds wakes the thread again with the files to watch.
+        if reload {
+            executor.spawn_job(Priority::Ui, |api| {
+                if let Err(error) = super::reload(api) {
+                    api.warn(&format!("failed to reload the config: {error}"));
+                }
+                Ok(())
+            });
+        }
+        if rewatch {
+            inotify.watch(&super::watched_files());
+        }
+    }
+}
+
+/// Watches the directories of every config file, since editors often replace files instead of writing to them.
+#[cfg(target_os = "linux")]
+struct Inotify {
+    fd: OwnedFd,
+    /// The directory of each watch descriptor.
+    directories: HashMap<libc::c_int, PathBuf>,
+    /// Every watched file, joined onto the directory it is watched from.
+    files: BTreeSet<PathBuf>,
+}
+#[cfg(target_os = "linux")]
+impl Inotify {
+    const MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE | libc::IN_DELETE;
+
+    fn new() -> io::Result<Self> {
+        let fd = check(unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) })?;
+        Ok(Self {
+            // SAFETY: the descriptor was just opened and is not owned by anything else.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            directories: HashMap::new(),
+            files: BTreeSet::new(),
+        })
+    }
+
+    fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Replace every watch with watches for the directories of `files`.
+    fn watch(&mut self, files: &[PathBuf]) {
+        mem::take(&mut self.directories)
+            .into_keys()
+            .for_each(|wd| unsafe {
+                libc::inotify_rm_watch(self.fd(), wd);
+            });
+
+        self.files = files
+            .iter()
+            .filter_map(|file| {
+                let directory = match file.parent() {
+                    Some(parent) if parent != Path::new("") => parent,
+                    _ => Path::new("."),
+                };
+                Some(directory.join(file.file_name()?))
+            })
+            .collect();
+
+        let directories = self
+            .files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect::<BTreeSet<_>>();
+        directories.into_iter().for_each(|directory| {
+            let result = CString::new(directory.as_os_str().as_encoded_bytes())
+                .map_err(io::Error::from)
+                .and_then(|path| {
+                    check(unsafe { libc::inotify_add_watch(self.fd(), path.as_ptr(), Self::MASK) })
+                });
+            match result {
+                Ok(wd) => {
+                    self.directories.insert(wd, directory);
+                }
+                Err(error) => eprintln!("failed to watch `{}`: {error}", directory.display()),
+            }
+        });
+    }
+
+    /// Read every pending event, returning whether any watched file changed.
+    fn changed(&mut self) -> bool {
+        let events = drain(self.fd());
+        let mut changed = false;
+        let mut offset = 0;
+
+        while let Some(header) = events.get(offset..offset + mem::size_of::<inotify_event>()) {
+            // SAFETY: the kernel only writes whole events, and the header was bounds checked above.
+            let event = unsafe { ptr::read_unaligned(header.as_ptr().cast::<inotify_event>()) };
+            let name_start = offset + header.len();
+            offset = name_start + event.len as usize;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                changed = true;
+            } else if let (Some(directory), Some(name)) = (
+                self.directories.get(&event.wd),
+                events.get(name_start..offset),
+            ) {
+                let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+                // SAFETY: the name came from the kernel, which got it from an [OsStr].
+                let name = unsafe { std::ffi::OsStr::from_encoded_bytes_unchecked(name) };
+                changed |= self.files.contains(&directory.join(name));
+            }
+        }
+
+        changed
+    }
+}
+
+/// Files are only watched on linux, so this only ever waits for SIGHUP.
+#[cfg(not(target_os = "linux"))]
+struct Inotify;
+#[cfg(not(target_os = "linux"))]
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        Ok(Self)
+    }
+
+    fn fd(&self) -> RawFd {
+        -1
+    }
+
+    fn watch(&mut self, _: &[std::path::PathBuf]) {}
+
+    fn changed(&mut self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,477 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Player events, which are dispatched to hooks in the `(empl hooks)` module.
+
+use {
+    crate::{
+        config::check,
+        guile::{
+            Api, Scm, convert::IntoScm, error::GuileError, executor::Executor, guile_fn,
+            protected::ProtectedScm,
+        },
+    },
+    parking_lot::{Mutex, RwLock},
+    std::{
+        ffi::CStr,
+        path::PathBuf,
+        sync::{
+            Arc,
+            mpsc::{self, SendError, Sender},
+        },
+        thread::{self, JoinHandle},
+        time::Duration,
+    },
+};
+
+/// The module that every hook is exported from.
+pub const MODULE: &CStr = c"empl hooks";
+
+/// The name and arity of the hook for each event, in the order of [Event::index].
+const HOOKS: [(&CStr, u8); Event::COUNT] = [
+    (c"track-started-hook", 1),
+    (c"paused-hook", 0),
+    (c"resumed-hook", 0),
+    (c"seeked-hook", 1),
+    (c"queue-changed-hook", 0),
+    (c"volume-changed-hook", 1),
+    (c"library-rescanned-hook", 0),
+    (c"shutdown-hook", 0),
+];
+
+type Hooks = Arc<[ProtectedScm; Event::COUNT]>;
+
+/// The hooks that events run, created by [Api::define_hooks] and swapped by [Api::replace_hooks].
+///
+/// The lock is only held long enough to clone the [Arc], so hooks can be run and replaced from inside other hooks.
+static HOOK_OBJECTS: RwLock<Option<Hooks>> = RwLock::new(None);
+/// The hooks that `(empl hooks)` exports, which differ from [HOOK_OBJECTS] while [Api::replace_hooks] is loading.
+static EXPORTED_HOOKS: RwLock<Option<Hooks>> = RwLock::new(None);
+
+/// How long each procedure in a hook may run before it is interrupted, set with `set-hook-deadline!`.
+static HOOK_DEADLINE: Mutex<Option<Duration>> = Mutex::new(None);
+
+/// Interrupt any procedure run from a hook after `seconds`, or never if `seconds` is `#f`.
+///
+/// Interrupted procedures throw a `timeout` exception, which is reported like any other error in a hook.
+#[guile_fn(guile_ident = "set-hook-deadline!", module = "empl hooks")]
+fn set_hook_deadline(api: &mut Api, seconds: Option<f64>) -> Result<(), GuileError> {
+    let deadline = seconds
+        .map(|seconds| {
+            Duration::try_from_secs_f64(seconds)
+                .ok()
+                .filter(|deadline| !deadline.is_zero())
+                .ok_or_else(|| GuileError::out_of_range(api, seconds.into_scm(api)))
+        })
+        .transpose()?;
+    if check::record_effect(|| match seconds {
+        Some(seconds) => format!("interrupt hooks after {seconds} seconds"),
+        None => "stop interrupting hooks".to_owned(),
+    }) {
+        return Ok(());
+    }
+
+    *HOOK_DEADLINE.lock() = deadline;
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Runs `track-started-hook` with the path of the track.
+    TrackStarted(PathBuf),
+    /// Runs `paused-hook`.
+    Paused,
+    /// Runs `resumed-hook`.
+    Resumed,
+    /// Runs `seeked-hook` with the new position in seconds.
+    Seeked(Duration),
+    /// Runs `queue-changed-hook`.
+    QueueChanged,
+    /// Runs `volume-changed-hook` with the new volume.
+    VolumeChanged(f64),
+    /// Runs `library-rescanned-hook` once a rescan of the library has finished.
+    LibraryRescanned,
+    /// Runs `shutdown-hook`.
+    Shutdown,
+}
+impl Event {
+    const COUNT: usize = 8;
+
+    const fn index(&self) -> usize {
+        match self {
+            Self::TrackStarted(_) => 0,
+            Self::Paused => 1,
+            Self::Resumed => 2,
+            Self::Seeked(_) => 3,
+            Self::QueueChanged => 4,
+            Self::VolumeChanged(_) => 5,
+            Self::LibraryRescanned => 6,
+            Self::Shutdown => 7,
+        }
+    }
+
+    /// The name of the hook that this event runs.
+    pub const fn hook_name(&self) -> &'static CStr {
+        HOOKS[self.index()].0
+    }
+
+    fn args(&self, api: &Api) -> Vec<Scm> {
+        match self {
+            Self::TrackStarted(path) => vec![path.as_path().into_scm(api)],
+            Self::Seeked(position) => vec![position.as_secs_f64().into_scm(api)],
+            Self::VolumeChanged(volume) => vec![volume.into_scm(api)],
+            Self::Paused
+            | Self::Resumed
+            | Self::QueueChanged
+            | Self::LibraryRescanned
+            | Self::Shutdown => Vec::new(),
+        }
+    }
+}
+
+impl Api {
+    /// Define and export every hook in `(empl hooks)`.
+    pub fn define_hooks(&self) {
+        let hooks = HOOK_OBJECTS
+            .write()
+            .get_or_insert_with(|| self.make_hooks())
+            .clone();
+        self.export_hooks(&hooks);
+    }
+
+    fn make_hooks(&self) -> Hooks {
+        Arc::new(HOOKS.map(|(_, arity)| ProtectedScm::new(self, self.make_hook(arity))))
+    }
+
+    /// Point the variables in `(empl hooks)` at `hooks`.
+    fn export_hooks(&self, hooks: &Hooks) {
+        *EXPORTED_HOOKS.write() = Some(Arc::clone(hooks));
+        self.define_values(
+            MODULE,
+            &HOOKS
+                .iter()
+                .zip(hooks.iter())
+                .map(|((name, _), hook)| (*name, hook.get(self)))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    /// Run every procedure in the hook for `event`, returning the errors of the procedures that failed.
+    ///
+    /// Unlike `run-hook`, an error in one procedure does not stop the rest from running.
+    /// Each procedure is interrupted once the deadline set with `set-hook-deadline!` has passed.
+    /// Nothing is run if the hooks have not been defined with [Api::define_hooks].
+    pub fn run_hooks(&mut self, event: &Event) -> Vec<GuileError> {
+        let Some(hooks) = HOOK_OBJECTS.read().clone() else {
+            return Vec::new();
+        };
+        let procedures = self.protected_procedures(hooks[event.index()].get(self));
+        let args = event.args(self);
+
+        let deadline = *HOOK_DEADLINE.lock();
+        procedures
+            .iter()
+            .filter_map(|procedure| {
+                // Guarding every call is only worth it when there is a deadline to enforce.
+                match deadline {
+                    Some(_) => {
+                        self.with_deadline(deadline, |api| procedure.get(api).call(api, &args))
+                    }
+                    None => procedure.get(self).call(self, &args),
+                }
+                .err()
+            })
+            .collect()
+    }
+
+    /// Export a new set of empty hooks and call `load`, which is expected to fill them.
+    ///
+    /// Events keep running the previous hooks until `load` succeeds, and the previous hooks are exported again if it fails.
+    pub fn replace_hooks<F, T, E>(&mut self, load: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Self) -> Result<T, E>,
+    {
+        let Some(previous) = EXPORTED_HOOKS.read().clone() else {
+            return load(self);
+        };
+        let hooks = self.make_hooks();
+        self.export_hooks(&hooks);
+
+        let output = load(self);
+        // `load` may have replaced the hooks itself, so they are exported again either way.
+        match output {
+            Ok(_) => {
+                self.export_hooks(&hooks);
+                *HOOK_OBJECTS.write() = Some(hooks);
+            }
+            Err(_) => self.export_hooks(&previous),
+        }
+
+        output
+    }
+
+    /// The name of every hook that `(empl hooks)` exports, along with the number of procedures that have been added to it.
+    pub fn hook_sizes(&self) -> Vec<(&'static CStr, usize)> {
+        EXPORTED_HOOKS
+            .read()
+            .clone()
+            .map_or_else(Vec::new, |hooks| {
+                HOOKS
+                    .iter()
+                    .zip(hooks.iter())
+                    .map(|((name, _), hook)| (*name, self.hook_procedures(hook.get(self)).len()))
+                    .collect()
+            })
+    }
+
+    /// The procedures in `hook`, protected so they outlive being removed from it.
+    fn protected_procedures(&self, hook: Scm) -> Vec<ProtectedScm> {
+        self.hook_procedures(hook)
+            .into_iter()
+            .map(|procedure| ProtectedScm::new(self, procedure))
+            .collect()
+    }
+}
+
+/// Run the hooks for `event`, reporting the procedures that failed on `(current-error-port)`.
+pub fn dispatch(api: &mut Api, event: &Event) {
+    api.run_hooks(event).into_iter().for_each(|error| {
+        api.warn(&format!(
+            "error in `{}`: {error}",
+            event.hook_name().to_string_lossy()
+        ))
+    });
+}
+
+/// A handle for sending events to the thread that forwards them to the [Executor].
+///
+/// Sending never waits on the executor, so it is safe from threads that must not block, such as the audio thread.
+#[derive(Clone, Debug)]
+pub struct EventBus(Sender<Event>);
+impl EventBus {
+    /// Spawn the thread that sends every event on the bus to `executor` with [Executor::send_event].
+    ///
+    /// The thread exits, dropping `executor`, once every [EventBus] has been dropped and the remaining events have been forwarded.
+    pub fn spawn(executor: Executor) -> (Self, JoinHandle<()>) {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        let handle = thread::spawn(move || {
+            receiver
+                .into_iter()
+                .for_each(|event| executor.send_event(event))
+        });
+
+        (Self(sender), handle)
+    }
+
+    /// # Errors
+    ///
+    /// Fails if the dispatch thread has exited.
+    pub fn send(&self, event: Event) -> Result<(), SendError<Event>> {
+        self.0.send(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile::{self, convert::FromScm, executor::Priority},
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK},
+        },
+    };
+
+    #[test]
+    fn hook_names() {
+        assert_eq!(
+            Event::TrackStarted(PathBuf::new()).hook_name(),
+            c"track-started-hook"
+        );
+        assert_eq!(Event::Shutdown.hook_name(), c"shutdown-hook");
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn errors_do_not_stop_hooks() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        guile::with_guile(|api| {
+            api.define_hooks();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl hooks))
+                    (define volumes '())
+                    (add-hook! volume-changed-hook (lambda (volume) (set! volumes (cons volume volumes))))
+                    (add-hook! volume-changed-hook (lambda (volume) (error \"boom\"))))",
+            );
+
+            let errors = api.run_hooks(&Event::VolumeChanged(0.5));
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].message().contains("boom"));
+            assert_eq!(
+                Vec::<f64>::from_scm(api, api.eval_cstring(c"volumes")).unwrap(),
+                [0.5]
+            );
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn hook_deadline() {
+        // Hooks are shared with every other test.
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        guile::with_guile(|api| {
+            api.define_bindings();
+            api.define_hooks();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl hooks))
+                    (set-hook-deadline! 0.05)
+                    (add-hook! queue-changed-hook (lambda () (let loop () (loop)))))",
+            );
+
+            let errors = api.run_hooks(&Event::QueueChanged);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].key(), "timeout");
+
+            assert_eq!(
+                api.try_eval_cstring(c"(set-hook-deadline! -1)")
+                    .unwrap_err()
+                    .key(),
+                "out-of-range"
+            );
+            api.eval_cstring(c"(begin (set-hook-deadline! #f) (reset-hook! queue-changed-hook))");
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn replace_hooks() {
+        // Hooks are shared with every other test.
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        guile::with_guile(|api| {
+            api.define_hooks();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl hooks))
+                    (define (paused-results) (map (lambda (procedure) (procedure)) (hook->list paused-hook)))
+                    (define old-runs 0)
+                    (add-hook! paused-hook (lambda () (set! old-runs (+ old-runs 1)) 'old)))",
+            );
+
+            let failed = api.replace_hooks(|api| {
+                assert!(api.eval_cstring(c"(null? (paused-results))").is_true());
+                api.eval_cstring(c"(add-hook! paused-hook (lambda () 'new))");
+
+                // Events still run the previous hooks, and hooks can be replaced again without deadlocking.
+                assert!(api.run_hooks(&Event::Paused).is_empty());
+                assert!(api.eval_cstring(c"(= old-runs 1)").is_true());
+                api.replace_hooks(|_| Err::<(), _>(())).unwrap_err();
+                Err::<(), _>("boom")
+            });
+            assert_eq!(failed, Err("boom"));
+            assert!(
+                api.eval_cstring(c"(equal? (paused-results) '(old))")
+                    .is_true()
+            );
+
+            api.replace_hooks(|api| {
+                api.eval_cstring(c"(add-hook! paused-hook (lambda () 'new))");
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+            assert!(
+                api.eval_cstring(c"(equal? (paused-results) '(new))")
+                    .is_true()
+            );
+
+            api.eval_cstring(c"(reset-hook! paused-hook)");
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn event_bus() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        guile::with_guile(|api| {
+            api.define_hooks();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl hooks))
+                    (define started '())
+                    (add-hook! track-started-hook (lambda (track) (set! started (cons track started)))))",
+            );
+        });
+
+        let (executor, executor_handle) = Executor::spawn();
+        let (bus, handle) = EventBus::spawn(executor);
+        bus.send(Event::TrackStarted(PathBuf::from("foo.flac")))
+            .unwrap();
+        bus.send(Event::Shutdown).unwrap();
+        drop(bus);
+        handle.join().unwrap();
+        executor_handle.join().unwrap();
+
+        guile::with_guile(|api| {
+            assert_eq!(
+                Vec::<String>::from_scm(api, api.eval_cstring(c"started")).unwrap(),
+                ["foo.flac"]
+            );
+            api.eval_cstring(c"(reset-hook! track-started-hook)");
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn executor_events() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.read();
+
+        let (executor, handle) = Executor::spawn();
+        executor
+            .execute(Priority::Ui, |api| {
+                api.define_hooks();
+                api.eval_cstring(
+                    c"(begin
+                        (use-modules (empl hooks))
+                        (define positions '())
+                        (add-hook! seeked-hook (lambda (position) (set! positions (cons position positions)))))",
+                );
+            })
+            .wait()
+            .unwrap();
+
+        executor.send_event(Event::Seeked(Duration::from_millis(1500)));
+        let positions = executor.execute(Priority::Bulk, |api| {
+            let positions = Vec::<f64>::from_scm(api, api.eval_cstring(c"positions")).unwrap();
+            api.eval_cstring(c"(reset-hook! seeked-hook)");
+            positions
+        });
+        assert_eq!(positions.wait().unwrap(), [1.5]);
+
+        drop(executor);
+        handle.join().unwrap();
+    }
+}
@@ -16,25 +16,37 @@
 // You should have received a copy of the GNU General Public License
 // along with empl.  If not, see <http://www.gnu.org/licenses/>.
 
+pub mod convert;
+pub mod error;
+pub mod executor;
+pub mod foreign;
+pub mod interrupt;
+pub mod procedure;
+pub mod protected;
+pub mod registry;
+
 use {
-    crate::guile,
-    parking_lot::Mutex,
+    crate::guile::{self, error::GuileError, protected::ProtectedScm},
     std::{
-        ffi::{CStr, c_int, c_void},
+        ffi::{CStr, c_char, c_int, c_void},
         marker::PhantomData,
-        ptr,
-        sync::atomic::{self, AtomicBool},
+        ptr, slice,
+        sync::{
+            Once, OnceLock,
+            atomic::{self, AtomicBool},
+        },
     },
 };
 
 pub use proc_macros::guile_fn;
 
-/// Lock for thread initialization
-static INIT_LOCK: Mutex<()> = const { Mutex::new(()) };
+/// Guile's own initialization is not thread safe, so only one thread may enter guile until it has finished.
+///
+/// Threads only wait for initialization rather than for the first [with_guile] call to return.
+/// The main thread stays in its first call for as long as the config loads, so the watchdog that interrupts the config would otherwise never get to enter guile.
+static INIT: Once = Once::new();
 
 thread_local! {
-    /// Whether the current thread has been initiated yet
-    static INIT: AtomicBool = const { AtomicBool::new(false) };
     /// Whether the current thread is currently in guile mode.
     static GUILE_MODE: AtomicBool = const { AtomicBool::new(false) };
 }
@@ -48,21 +60,93 @@ impl Api {
         Self(())
     }
 
+    /// Define and export `F` in its module, creating the module if it does not exist yet.
     pub fn define_fn<F>(&self)
     where
         F: GuileFn,
     {
+        unsafe extern "C" fn define<F>(_: *mut c_void)
+        where
+            F: GuileFn,
+        {
+            let api = unsafe { Api::new_unchecked() };
+            unsafe {
+                let procedure = guile::sys::scm_c_define_gsubr(
+                    F::NAME.as_ptr(),
+                    F::REQUIRED as c_int,
+                    F::OPTIONAL as c_int,
+                    F::REST.into(),
+                    F::DRIVER,
+                );
+                if let Some(doc) = F::DOC {
+                    guile::sys::scm_set_procedure_property_x(
+                        procedure,
+                        api.make_symbol("documentation").0,
+                        api.make_string(doc).0,
+                    );
+                }
+                guile::sys::scm_c_export(F::NAME.as_ptr(), ptr::null::<c_char>());
+            }
+        }
+
+        unsafe {
+            guile::sys::scm_c_define_module(F::MODULE.as_ptr(), Some(define::<F>), ptr::null_mut());
+        }
+    }
+
+    /// Define and export every value in `module`, creating the module if it does not exist yet.
+    pub fn define_values(&self, module: &CStr, values: &[(&CStr, Scm)]) {
+        /// # Safety
+        ///
+        /// `data` must be a pointer of type `&[(&CStr, Scm)]`
+        unsafe extern "C" fn define(data: *mut c_void) {
+            unsafe { data.cast::<&[(&CStr, Scm)]>().as_ref() }
+                .into_iter()
+                .flat_map(|values| values.iter())
+                .for_each(|(name, Scm(value))| unsafe {
+                    guile::sys::scm_c_define(name.as_ptr(), *value);
+                    guile::sys::scm_c_export(name.as_ptr(), ptr::null::<c_char>());
+                });
+        }
+
         unsafe {
-            guile::sys::scm_c_define_gsubr(
-                F::NAME.as_ptr(),
-                F::REQUIRED as c_int,
-                F::OPTIONAL as c_int,
-                F::REST.into(),
-                F::DRIVER,
+            guile::sys::scm_c_define_module(
+                module.as_ptr(),
+                Some(define),
+                (&raw const values).cast_mut().cast(),
             );
         }
     }
 
+    /// Import the public interface of `module` into the current module.
+    pub fn use_module(&self, module: &CStr) {
+        unsafe { sys::scm_c_use_module(module.as_ptr()) };
+    }
+
+    /// Create a hook whose procedures take `arity` arguments.
+    pub fn make_hook(&self, arity: u8) -> Scm {
+        Scm::new(unsafe { sys::scm_make_hook(sys::scm_from_int64(arity.into())) })
+    }
+
+    /// The procedures that have been added to `hook`, in the order that `run-hook` calls them.
+    pub fn hook_procedures(&self, Scm(hook): Scm) -> Vec<Scm> {
+        self.list_to_vec(Scm(unsafe { sys::scm_hook_to_list(hook) }))
+            .unwrap_or_default()
+            .into_iter()
+            .map(Scm)
+            .collect()
+    }
+
+    /// Remove every procedure from `hook`.
+    pub fn reset_hook(&self, Scm(hook): Scm) {
+        unsafe { sys::scm_reset_hook_x(hook) };
+    }
+
+    /// Add `procedure` to the end of `hook`.
+    pub fn add_hook(&self, Scm(hook): Scm, Scm(procedure): Scm) {
+        unsafe { sys::scm_add_hook_x(hook, procedure, sys::REEXPORTS_SCM_BOOL_T) };
+    }
+
     pub fn eval_cstring<S>(&self, string: &S) -> Scm
     where
         S: AsRef<CStr> + ?Sized,
@@ -73,6 +157,199 @@ impl Api {
         Scm::new(unsafe { sys::scm_eval_string(string) })
     }
 
+    /// Read and evaluate every expression in the file at `path`.
+    pub fn load<S>(&self, path: &S) -> Scm
+    where
+        S: AsRef<CStr> + ?Sized,
+    {
+        Scm::new(unsafe { sys::scm_c_primitive_load(path.as_ref().as_ptr()) })
+    }
+
+    /// Evaluate a string, returning any exception thrown during evaluation as an error.
+    pub fn try_eval_cstring<S>(&mut self, string: &S) -> Result<Scm, GuileError>
+    where
+        S: AsRef<CStr> + ?Sized,
+    {
+        // SAFETY: nothing is held across the call into guile
+        unsafe { self.catch(|api| api.eval_cstring(string)) }
+    }
+    /// Evaluate a scheme string, returning any exception thrown during evaluation as an error.
+    pub fn try_eval_string(&mut self, string: Scm) -> Result<Scm, GuileError> {
+        // SAFETY: see above
+        unsafe { self.catch(|api| api.eval_string(string)) }
+    }
+    /// Read and evaluate every expression in the file at `path`, returning any exception thrown during evaluation as an error.
+    pub fn try_load<S>(&mut self, path: &S) -> Result<Scm, GuileError>
+    where
+        S: AsRef<CStr> + ?Sized,
+    {
+        // SAFETY: see above
+        unsafe { self.catch(|api| api.load(path)) }
+    }
+
+    /// Display `message` on its own line on `(current-error-port)`, so it is seen by whoever is evaluating, such as a repl client.
+    ///
+    /// Falls back to stderr if the port cannot be written to.
+    pub fn warn(&mut self, message: &str) {
+        static WARN: OnceLock<ProtectedScm> = OnceLock::new();
+        let warn = self.eval_once(
+            &WARN,
+            c"(lambda (message)
+                (display message (current-error-port))
+                (newline (current-error-port)))",
+        );
+
+        let message_scm = self.make_string(message);
+        if let Err(error) = warn.call(self, &[message_scm]) {
+            eprintln!("{message}");
+            eprintln!("failed to write to the current error port: {error}");
+        }
+    }
+
+    /// Run `operation`, catching every exception that is thrown inside of it.
+    ///
+    /// Only the safe wrappers such as [Api::try_eval_cstring] and [Scm::call] are public.
+    ///
+    /// # Safety
+    ///
+    /// A throw jumps straight out of `operation` without running any destructors.
+    /// `operation` must not own anything that needs to be dropped, such as a [String] or a lock guard, while it calls into guile.
+    unsafe fn catch<F>(&mut self, operation: F) -> Result<Scm, GuileError>
+    where
+        F: FnOnce(&mut Api) -> Scm,
+    {
+        #[derive(Default)]
+        struct HandlerData {
+            backtrace: Option<String>,
+            error: Option<GuileError>,
+        }
+
+        /// # Safety
+        ///
+        /// `data` must be a pointer of type `Option<F>`
+        unsafe extern "C" fn body<F>(data: *mut c_void) -> sys::SCM
+        where
+            F: FnOnce(&mut Api) -> Scm,
+        {
+            unsafe { data.cast::<Option<F>>().as_mut() }
+                .and_then(Option::take)
+                .map_or(unsafe { sys::REEXPORTS_SCM_BOOL_F }, |operation| {
+                    operation(&mut Api(())).0
+                })
+        }
+        /// Runs before the stack is unwound so the backtrace still contains the frames that threw.
+        ///
+        /// # Safety
+        ///
+        /// `data` must be a pointer of type `HandlerData`
+        unsafe extern "C" fn pre_unwind_handler(
+            data: *mut c_void,
+            _: sys::SCM,
+            _: sys::SCM,
+        ) -> sys::SCM {
+            if let Some(data) = unsafe { data.cast::<HandlerData>().as_mut() } {
+                data.backtrace = Api(()).render_backtrace();
+            }
+
+            unsafe { sys::REEXPORTS_SCM_BOOL_F }
+        }
+        /// # Safety
+        ///
+        /// `data` must be a pointer of type `HandlerData`
+        unsafe extern "C" fn handler(data: *mut c_void, key: sys::SCM, args: sys::SCM) -> sys::SCM {
+            if let Some(data) = unsafe { data.cast::<HandlerData>().as_mut() } {
+                data.error = Some(
+                    GuileError::from_throw(&Api(()), Scm(key), Scm(args))
+                        .with_backtrace(data.backtrace.take()),
+                );
+            }
+
+            unsafe { sys::REEXPORTS_SCM_BOOL_F }
+        }
+
+        let mut operation = Some(operation);
+        let mut data = HandlerData::default();
+        let output = unsafe {
+            sys::scm_c_catch(
+                sys::REEXPORTS_SCM_BOOL_T,
+                Some(body::<F>),
+                (&raw mut operation).cast(),
+                Some(handler),
+                (&raw mut data).cast(),
+                Some(pre_unwind_handler),
+                (&raw mut data).cast(),
+            )
+        };
+
+        data.error.map_or(Ok(Scm(output)), Err)
+    }
+
+    /// Render the current stack the same way that guile does for uncaught exceptions.
+    fn render_backtrace(&self) -> Option<String> {
+        let stack =
+            unsafe { sys::scm_make_stack(sys::REEXPORTS_SCM_BOOL_T, sys::REEXPORTS_SCM_EOL) };
+        if stack == unsafe { sys::REEXPORTS_SCM_BOOL_F } {
+            return None;
+        }
+
+        let port = unsafe { sys::scm_open_output_string() };
+        unsafe {
+            sys::scm_display_backtrace(
+                stack,
+                port,
+                sys::REEXPORTS_SCM_BOOL_F,
+                sys::REEXPORTS_SCM_BOOL_F,
+            );
+        }
+
+        Some(self.to_utf8(Scm(unsafe { sys::scm_get_output_string(port) })))
+    }
+
+    /// Copy a scheme string into a rust string.
+    fn to_utf8(&self, Scm(string): Scm) -> String {
+        let bytevector = unsafe { sys::scm_string_to_utf8(string) };
+        // SAFETY: the contents of a bytevector are valid for its entire length
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                sys::reexports_scm_bytevector_contents(bytevector).cast::<u8>(),
+                sys::scm_c_bytevector_length(bytevector),
+            )
+        };
+
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    /// Get the name of a symbol, or the written representation of anything else.
+    fn symbol_name(&self, Scm(scm): Scm) -> String {
+        if unsafe { sys::scm_is_symbol(scm) } != 0 {
+            self.to_utf8(Scm(unsafe { sys::scm_symbol_to_string(scm) }))
+        } else {
+            self.write_to_string(Scm(scm))
+        }
+    }
+
+    /// Get the representation of an object that `write` would output.
+    fn write_to_string(&self, Scm(scm): Scm) -> String {
+        self.to_utf8(Scm(unsafe {
+            sys::scm_object_to_string(scm, sys::REEXPORTS_SCM_UNDEFINED)
+        }))
+    }
+
+    /// Copy the items of a proper list, returning [None] for anything else.
+    fn list_to_vec(&self, Scm(mut list): Scm) -> Option<Vec<sys::SCM>> {
+        usize::try_from(unsafe { sys::scm_ilength(list) })
+            .ok()
+            .map(|len| {
+                (0..len)
+                    .map(|_| {
+                        let car = unsafe { sys::scm_car(list) };
+                        list = unsafe { sys::scm_cdr(list) };
+                        car
+                    })
+                    .collect()
+            })
+    }
+
     pub fn make_string<S>(&self, string: &S) -> Scm
     where
         S: AsRef<str> + ?Sized,
@@ -80,6 +357,20 @@ impl Api {
         let string = string.as_ref();
         Scm::new(unsafe { guile::sys::scm_from_utf8_stringn(string.as_ptr().cast(), string.len()) })
     }
+    pub fn make_symbol<S>(&self, name: &S) -> Scm
+    where
+        S: AsRef<str> + ?Sized,
+    {
+        let name = name.as_ref();
+        Scm::new(unsafe { guile::sys::scm_from_utf8_symboln(name.as_ptr().cast(), name.len()) })
+    }
+    /// Create a keyword such as `#:path` from its name without the leading `#:`.
+    pub fn make_keyword<S>(&self, name: &S) -> Scm
+    where
+        S: AsRef<str> + ?Sized,
+    {
+        Scm::new(unsafe { sys::scm_symbol_to_keyword(self.make_symbol(name).0) })
+    }
     pub const fn make_false(&self) -> Scm {
         Scm(unsafe { sys::REEXPORTS_SCM_BOOL_F })
     }
@@ -164,14 +455,8 @@ where
     if GUILE_MODE.with(|mode| mode.load(atomic::Ordering::Acquire)) {
         operation(&mut guile::Api(()))
     } else {
-        let _lock = INIT
-            .with(|init| !init.load(atomic::Ordering::Acquire))
-            .then(|| INIT_LOCK.lock());
-
-        WithGuile::call(|api| {
-            INIT.with(|init| init.store(true, atomic::Ordering::Release));
-            operation(api)
-        })
+        INIT.call_once(|| WithGuile::call(|_| ()));
+        WithGuile::call(operation)
     }
 }
 
@@ -226,9 +511,16 @@ impl PartialEq for Scm {
 pub trait GuileFn {
     const REQUIRED: usize;
     const OPTIONAL: usize;
+    /// Also set for functions with keyword parameters, which are parsed from the rest list.
     const REST: bool;
+    /// The names of the keyword parameters without the leading `#:`.
+    const KEYWORDS: &[&str];
 
     const NAME: &CStr;
+    /// The module that the function is exported from, with the names separated by spaces, such as `empl player`.
+    const MODULE: &CStr;
+    /// The doc comments of the function, which are shown by `procedure-documentation`.
+    const DOC: Option<&str>;
     const DRIVER: sys::scm_t_subr;
 }
 
@@ -246,7 +538,11 @@ mod tests {
     use {
         super::*,
         crate::{
-            guile::{Scm, guile_fn},
+            guile::{
+                Scm,
+                convert::{FromScm, Rest},
+                guile_fn,
+            },
             tests::ENV_VAR_LOCK,
         },
         std::sync::atomic::{self, AtomicBool},
@@ -262,6 +558,9 @@ mod tests {
         assert_eq!(Foo::OPTIONAL, 1);
         assert_eq!(Foo::REST, false);
         assert_eq!(Foo::NAME, c"foo");
+        assert_eq!(Foo::MODULE, c"empl");
+        assert!(Foo::KEYWORDS.is_empty());
+        assert_eq!(Foo::DOC, None);
 
         #[guile_fn]
         fn bar(_: &mut Api, _: [Scm; 0], _: [Option<Scm>; 1]) -> Scm {
@@ -280,6 +579,172 @@ mod tests {
         assert_eq!(Baz::OPTIONAL, 0);
         assert_eq!(Baz::REST, true);
         assert_eq!(Baz::NAME, c"baz");
+
+        #[guile_fn]
+        fn many(_: &mut Api, _: [Scm; 4], _: [Option<Scm>; 4]) -> Scm {
+            unimplemented!()
+        }
+        assert_eq!(Many::REQUIRED, 4);
+        assert_eq!(Many::OPTIONAL, 4);
+    }
+
+    #[test]
+    fn typed_guile_fn_impl() {
+        /// Set the volume.
+        ///
+        /// The volume fades over `fade` milliseconds.
+        #[guile_fn]
+        fn set_volume(_: &mut Api, _level: f64, _fade: Option<u32>) -> bool {
+            unimplemented!()
+        }
+        assert_eq!(SetVolume::REQUIRED, 1);
+        assert_eq!(SetVolume::OPTIONAL, 1);
+        assert_eq!(SetVolume::REST, false);
+        assert_eq!(SetVolume::NAME, c"set-volume");
+        assert_eq!(
+            SetVolume::DOC,
+            Some("Set the volume.\n\nThe volume fades over `fade` milliseconds.")
+        );
+
+        #[guile_fn]
+        fn sum(_: &mut Api, _: Rest<i64>) -> i64 {
+            unimplemented!()
+        }
+        assert_eq!(Sum::REQUIRED, 0);
+        assert_eq!(Sum::OPTIONAL, 0);
+        assert_eq!(Sum::REST, true);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn typed_arguments() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        #[guile_fn]
+        fn add(_: &mut Api, x: i64, y: Option<i64>, Rest(rest): Rest<i64>) -> i64 {
+            x + y.unwrap_or_default() + rest.into_iter().sum::<i64>()
+        }
+
+        guile::with_guile(|api| {
+            api.define_fn::<Add>();
+            api.eval_cstring(c"(use-modules (empl))");
+
+            assert!(api.eval_cstring(c"(= (add 1) 1)").is_true());
+            assert!(api.eval_cstring(c"(= (add 1 #f) 1)").is_true());
+            assert!(api.eval_cstring(c"(= (add 1 2 3 4) 10)").is_true());
+
+            let error = api.try_eval_cstring(c"(add 1 \"foo\")").unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+            assert_eq!(error.subr(), Some("add"));
+            assert!(error.message().contains("argument 2 (`y`)"));
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn keyword_arguments() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        #[guile_fn]
+        fn play(
+            _: &mut Api,
+            track: String,
+            #[key] from: Option<f64>,
+            #[key(default = 1.0)] fade: f64,
+        ) -> String {
+            format!("{track} {from:?} {fade}")
+        }
+        assert_eq!(Play::REQUIRED, 1);
+        const { assert!(Play::REST) };
+        assert_eq!(Play::KEYWORDS, ["from", "fade"]);
+
+        guile::with_guile(|api| {
+            api.define_fn::<Play>();
+            api.eval_cstring(c"(use-modules (empl))");
+
+            [
+                (c"(play \"foo\")", "foo None 1"),
+                (c"(play \"foo\" #:fade 2)", "foo None 2"),
+                (c"(play \"foo\" #:fade 2 #:from 30)", "foo Some(30.0) 2"),
+            ]
+            .into_iter()
+            .for_each(|(expr, output)| {
+                assert_eq!(
+                    String::from_scm(api, api.eval_cstring(expr)).unwrap(),
+                    output
+                );
+            });
+
+            let error = api
+                .try_eval_cstring(c"(play \"foo\" #:volume 2)")
+                .unwrap_err();
+            assert_eq!(error.key(), "keyword-argument-error");
+            assert_eq!(error.subr(), Some("play"));
+
+            let error = api
+                .try_eval_cstring(c"(play \"foo\" #:fade \"slow\")")
+                .unwrap_err();
+            assert!(error.message().contains("argument 3 (`fade`)"));
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn panics() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        #[guile_fn]
+        fn explode(_: &mut Api) {
+            panic!("boom");
+        }
+
+        guile::with_guile(|api| {
+            api.define_fn::<Explode>();
+            api.eval_cstring(c"(use-modules (empl))");
+
+            let error = api.try_eval_cstring(c"(explode)").unwrap_err();
+            assert_eq!(error.key(), "rust-panic");
+            assert_eq!(error.subr(), Some("explode"));
+            assert_eq!(error.message(), "boom");
+
+            assert!(api.try_eval_cstring(c"#t").unwrap().is_true());
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn raise() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+        struct Guard;
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                DROPPED.store(true, atomic::Ordering::Release);
+            }
+        }
+
+        #[guile_fn]
+        fn fail(api: &mut Api, irritant: i32) -> Result<(), GuileError> {
+            let _guard = Guard;
+            Err(GuileError::new("my-error", "failed with ~a").with_irritant(api, irritant))
+        }
+
+        guile::with_guile(|api| {
+            api.define_fn::<Fail>();
+            api.eval_cstring(c"(use-modules (empl))");
+
+            let error = api.try_eval_cstring(c"(fail 1)").unwrap_err();
+            assert_eq!(error.key(), "my-error");
+            assert_eq!(error.subr(), None);
+            assert_eq!(error.message(), "failed with ~a 1");
+            assert_eq!(error.irritants().len(), 1);
+            assert_eq!(
+                i32::from_scm(api, error.irritants()[0].get(api)).unwrap(),
+                1
+            );
+        });
+        assert!(DROPPED.load(atomic::Ordering::Acquire));
     }
 
     #[cfg_attr(miri, ignore)]
@@ -304,6 +769,7 @@ mod tests {
         guile::with_guile(|api| {
             api.define_fn::<AssertIsNone>();
             api.define_fn::<AssertIsSome>();
+            api.eval_cstring(c"(use-modules (empl))");
 
             api.eval_cstring(c"(assert-is-none)");
             api.eval_cstring(c"(assert-is-some 1)");
@@ -317,7 +783,8 @@ mod tests {
 
         static EXECUTED: AtomicBool = AtomicBool::new(false);
 
-        #[guile_fn(guile_ident = "set-executed!")]
+        /// Set `EXECUTED`.
+        #[guile_fn(guile_ident = "set-executed!", module = "empl test")]
         fn set_executed(api: &mut Api, _: [Scm; 0], _: [Option<Scm>; 0]) -> Scm {
             EXECUTED.store(true, atomic::Ordering::Release);
             api.make_true()
@@ -325,7 +792,16 @@ mod tests {
 
         guile::with_guile(|api| {
             api.define_fn::<SetExecuted>();
+            assert!(!api.eval_cstring(c"(defined? 'set-executed!)").is_true());
+
+            api.eval_cstring(c"(use-modules (empl test))");
             api.eval_cstring(c"(set-executed!)");
+            assert!(
+                api.eval_cstring(
+                    c"(equal? (procedure-documentation set-executed!) \"Set `EXECUTED`.\")"
+                )
+                .is_true()
+            );
         });
 
         assert!(EXECUTED.load(atomic::Ordering::Acquire));
@@ -349,6 +825,15 @@ mod tests {
             .for_each(|thread| thread.join().unwrap());
     }
 
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn enter_during_first_call() {
+        let _lock = ENV_VAR_LOCK.read();
+        assert!(with_guile(|api| {
+            api.without_guile(|| std::thread::spawn(|| with_guile(|_| true)).join().unwrap())
+        }));
+    }
+
     #[cfg_attr(miri, ignore)]
     #[test]
     fn without_guile_nesting() {
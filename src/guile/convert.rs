@@ -0,0 +1,630 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Conversions between rust values and scheme values.
+
+#[cfg(unix)]
+use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+use {
+    crate::guile::{Api, Scm, error::GuileError, sys},
+    std::{
+        collections::HashMap,
+        hash::Hash,
+        path::{Path, PathBuf},
+        ptr, slice,
+    },
+};
+
+/// Types that can be converted into a scheme value.
+///
+/// [Option]s are represented as `#f` when they are [None], unless `#f` is a value of the type inside, such as [bool].
+/// Then [None] is represented as the unspecified value instead, so that it can be told apart from `Some(false)`.
+pub trait IntoScm {
+    /// Whether `#f` can be produced, in which case [None] is represented as the unspecified value instead.
+    const HAS_FALSE: bool = false;
+
+    fn into_scm(self, api: &Api) -> Scm;
+}
+
+/// Types that can be extracted from a scheme value.
+///
+/// [Option]s are extracted from the same representations that [IntoScm] produces, so [None] is only extracted from `#f` when `#f` is not a value of the type inside.
+pub trait FromScm: Sized {
+    /// Whether `#f` can be accepted, in which case the unspecified value is converted into [None] instead.
+    const HAS_FALSE: bool = false;
+
+    /// # Errors
+    ///
+    /// Values of the wrong type produce a `wrong-type-arg` error, and numbers that do not fit produce an `out-of-range` error.
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError>;
+}
+
+/// Values returned from a [GuileFn][crate::guile::GuileFn].
+///
+/// This is implemented for everything that implements [IntoScm], and for [Result]s whose errors are raised.
+pub trait IntoScmResult {
+    fn into_scm_result(self, api: &Api) -> Result<Scm, GuileError>;
+}
+impl<T> IntoScmResult for T
+where
+    T: IntoScm,
+{
+    fn into_scm_result(self, api: &Api) -> Result<Scm, GuileError> {
+        Ok(self.into_scm(api))
+    }
+}
+impl<T, E> IntoScmResult for Result<T, E>
+where
+    T: IntoScm,
+    E: Into<GuileError>,
+{
+    fn into_scm_result(self, api: &Api) -> Result<Scm, GuileError> {
+        self.map(|output| output.into_scm(api)).map_err(E::into)
+    }
+}
+
+/// A scheme vector, as opposed to the list that [Vec] converts to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vector<T>(pub Vec<T>);
+
+/// A scheme bytevector, as opposed to the list that [`Vec<u8>`] converts to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bytevector(pub Vec<u8>);
+
+/// The rest list of a [GuileFn][crate::guile::GuileFn] with every item converted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+impl IntoScm for Scm {
+    fn into_scm(self, _: &Api) -> Scm {
+        self
+    }
+}
+impl FromScm for Scm {
+    fn from_scm(_: &Api, scm: Scm) -> Result<Self, GuileError> {
+        Ok(scm)
+    }
+}
+
+impl IntoScm for () {
+    fn into_scm(self, _: &Api) -> Scm {
+        Scm(unsafe { sys::REEXPORTS_SCM_UNSPECIFIED })
+    }
+}
+
+macro_rules! impl_integer {
+    ($via:ty, $from:ident, $to:ident, $is:ident, $($ty:ty),+ $(,)?) => {
+        $(
+            impl IntoScm for $ty {
+                fn into_scm(self, _: &Api) -> Scm {
+                    Scm(unsafe { sys::$from(self as $via) })
+                }
+            }
+            impl FromScm for $ty {
+                fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+                    if unsafe { sys::scm_is_exact_integer(scm.0) } == 0 {
+                        Err(GuileError::wrong_type_arg(api, "exact integer", scm))
+                    } else if unsafe { sys::$is(scm.0, <$via>::MIN, <$via>::MAX) } == 0 {
+                        Err(GuileError::out_of_range(api, scm))
+                    } else {
+                        <$ty>::try_from(unsafe { sys::$to(scm.0) })
+                            .map_err(|_| GuileError::out_of_range(api, scm))
+                    }
+                }
+            }
+        )+
+    };
+}
+impl_integer!(
+    i64,
+    scm_from_int64,
+    scm_to_int64,
+    scm_is_signed_integer,
+    i8,
+    i16,
+    i32,
+    i64,
+    isize,
+);
+impl_integer!(
+    u64,
+    scm_from_uint64,
+    scm_to_uint64,
+    scm_is_unsigned_integer,
+    u8,
+    u16,
+    u32,
+    u64,
+    usize,
+);
+
+macro_rules! impl_float {
+    ($($ty:ty),+ $(,)?) => {
+        $(
+            impl IntoScm for $ty {
+                fn into_scm(self, _: &Api) -> Scm {
+                    Scm(unsafe { sys::scm_from_double(self.into()) })
+                }
+            }
+            impl FromScm for $ty {
+                fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+                    if unsafe { sys::scm_is_real(scm.0) } == 0 {
+                        Err(GuileError::wrong_type_arg(api, "real number", scm))
+                    } else {
+                        Ok(unsafe { sys::scm_to_double(scm.0) } as $ty)
+                    }
+                }
+            }
+        )+
+    };
+}
+impl_float!(f32, f64);
+
+impl IntoScm for bool {
+    const HAS_FALSE: bool = true;
+
+    fn into_scm(self, api: &Api) -> Scm {
+        if self {
+            api.make_true()
+        } else {
+            api.make_false()
+        }
+    }
+}
+impl FromScm for bool {
+    const HAS_FALSE: bool = true;
+
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_is_bool(scm.0) } == 0 {
+            Err(GuileError::wrong_type_arg(api, "boolean", scm))
+        } else {
+            Ok(scm.0 != unsafe { sys::REEXPORTS_SCM_BOOL_F })
+        }
+    }
+}
+
+impl IntoScm for &str {
+    fn into_scm(self, api: &Api) -> Scm {
+        api.make_string(self)
+    }
+}
+impl IntoScm for String {
+    fn into_scm(self, api: &Api) -> Scm {
+        api.make_string(&self)
+    }
+}
+impl FromScm for String {
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_is_string(scm.0) } == 0 {
+            Err(GuileError::wrong_type_arg(api, "string", scm))
+        } else {
+            Ok(api.to_utf8(scm))
+        }
+    }
+}
+
+/// Paths are encoded with the locale's encoding, which is how guile passes file names to the OS, so paths that are not UTF-8 are not replaced.
+impl IntoScm for &Path {
+    fn into_scm(self, _: &Api) -> Scm {
+        let bytes = self.as_os_str().as_encoded_bytes();
+        Scm(unsafe { sys::scm_from_locale_stringn(bytes.as_ptr().cast(), bytes.len()) })
+    }
+}
+impl IntoScm for PathBuf {
+    fn into_scm(self, api: &Api) -> Scm {
+        self.as_path().into_scm(api)
+    }
+}
+/// Paths are decoded with the locale's encoding, like the [IntoScm] implementation for [Path].
+impl FromScm for PathBuf {
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_is_string(scm.0) } == 0 {
+            return Err(GuileError::wrong_type_arg(api, "string", scm));
+        }
+
+        // Nothing is copied when measuring the encoded length, so the pointer is never written to.
+        let len = unsafe { sys::scm_to_locale_stringbuf(scm.0, ptr::dangling_mut(), 0) };
+        let mut bytes = vec![0_u8; len];
+        unsafe { sys::scm_to_locale_stringbuf(scm.0, bytes.as_mut_ptr().cast(), len) };
+
+        #[cfg(unix)]
+        {
+            Ok(PathBuf::from(OsString::from_vec(bytes)))
+        }
+        #[cfg(not(unix))]
+        String::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| GuileError::wrong_type_arg(api, "utf8 path", scm))
+    }
+}
+
+impl IntoScm for &[u8] {
+    fn into_scm(self, _: &Api) -> Scm {
+        let bytevector = unsafe { sys::scm_c_make_bytevector(self.len()) };
+        // SAFETY: the bytevector was allocated with the same length
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.as_ptr(),
+                sys::reexports_scm_bytevector_contents(bytevector).cast(),
+                self.len(),
+            );
+        }
+
+        Scm(bytevector)
+    }
+}
+impl IntoScm for Bytevector {
+    fn into_scm(self, api: &Api) -> Scm {
+        self.0.as_slice().into_scm(api)
+    }
+}
+impl FromScm for Bytevector {
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_is_bytevector(scm.0) } == 0 {
+            Err(GuileError::wrong_type_arg(api, "bytevector", scm))
+        } else {
+            // SAFETY: the contents of a bytevector are valid for its entire length
+            Ok(Self(
+                unsafe {
+                    slice::from_raw_parts(
+                        sys::reexports_scm_bytevector_contents(scm.0).cast::<u8>(),
+                        sys::scm_c_bytevector_length(scm.0),
+                    )
+                }
+                .to_vec(),
+            ))
+        }
+    }
+}
+
+impl<T> IntoScm for Vec<T>
+where
+    T: IntoScm,
+{
+    fn into_scm(self, api: &Api) -> Scm {
+        Scm(self
+            .into_iter()
+            .rev()
+            .fold(unsafe { sys::REEXPORTS_SCM_EOL }, |cdr, car| unsafe {
+                sys::scm_cons(car.into_scm(api).0, cdr)
+            }))
+    }
+}
+impl<T> FromScm for Vec<T>
+where
+    T: FromScm,
+{
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        api.list_to_vec(scm)
+            .ok_or_else(|| GuileError::wrong_type_arg(api, "list", scm))?
+            .into_iter()
+            .map(|item| T::from_scm(api, Scm(item)))
+            .collect()
+    }
+}
+
+impl<T> FromScm for Rest<T>
+where
+    T: FromScm,
+{
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        Vec::from_scm(api, scm).map(Self)
+    }
+}
+
+/// Split the rest list of a [GuileFn][crate::guile::GuileFn] with keyword parameters, such as `(#:from 30 #:fade 2)`, into the value given for each of `names`.
+///
+/// Keywords that are given more than once take their last value.
+///
+/// # Errors
+///
+/// Unknown keywords, keywords without a value, and values without a keyword produce a `keyword-argument-error`.
+pub fn keyword_arguments<const N: usize>(
+    api: &Api,
+    rest: Scm,
+    names: [&str; N],
+) -> Result<[Option<Scm>; N], GuileError> {
+    let error = |message: String, irritant: sys::SCM| {
+        GuileError::new("keyword-argument-error", message).with_irritant(api, Scm(irritant))
+    };
+
+    let mut values = [None; N];
+    api.list_to_vec(rest)
+        .ok_or_else(|| GuileError::wrong_type_arg(api, "list", rest))?
+        .chunks(2)
+        .try_for_each(|pair| {
+            let keyword = pair[0];
+            if unsafe { sys::scm_is_keyword(keyword) } == 0 {
+                return Err(error("Invalid keyword:".to_string(), keyword));
+            }
+
+            let name = api.symbol_name(Scm(unsafe { sys::scm_keyword_to_symbol(keyword) }));
+            let position = names
+                .iter()
+                .position(|known| *known == name)
+                .ok_or_else(|| {
+                    error(
+                        format!(
+                            "Unrecognized keyword (expected one of {}):",
+                            names.map(|name| format!("`#:{name}`")).join(", ")
+                        ),
+                        keyword,
+                    )
+                })?;
+            let value = pair
+                .get(1)
+                .ok_or_else(|| error("Keyword argument has no value:".to_string(), keyword))?;
+            values[position] = Some(Scm(*value));
+
+            Ok(())
+        })
+        .map(|_| values)
+}
+
+impl<T> IntoScm for Vector<T>
+where
+    T: IntoScm,
+{
+    fn into_scm(self, api: &Api) -> Scm {
+        let vector = unsafe { sys::scm_c_make_vector(self.0.len(), sys::REEXPORTS_SCM_BOOL_F) };
+        self.0.into_iter().enumerate().for_each(|(i, item)| unsafe {
+            sys::scm_c_vector_set_x(vector, i, item.into_scm(api).0)
+        });
+
+        Scm(vector)
+    }
+}
+impl<T> FromScm for Vector<T>
+where
+    T: FromScm,
+{
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_is_vector(scm.0) } == 0 {
+            Err(GuileError::wrong_type_arg(api, "vector", scm))
+        } else {
+            (0..unsafe { sys::scm_c_vector_length(scm.0) })
+                .map(|i| T::from_scm(api, Scm(unsafe { sys::scm_c_vector_ref(scm.0, i) })))
+                .collect::<Result<_, _>>()
+                .map(Self)
+        }
+    }
+}
+
+/// [None] is represented as `#f`, or as the unspecified value if `T` can produce `#f` itself, such as [bool].
+impl<T> IntoScm for Option<T>
+where
+    T: IntoScm,
+{
+    const HAS_FALSE: bool = true;
+
+    fn into_scm(self, api: &Api) -> Scm {
+        match self {
+            Some(item) => item.into_scm(api),
+            None if T::HAS_FALSE => ().into_scm(api),
+            None => api.make_false(),
+        }
+    }
+}
+/// `#f` is converted into [None], or the unspecified value if `T` accepts `#f` itself, such as [bool].
+impl<T> FromScm for Option<T>
+where
+    T: FromScm,
+{
+    const HAS_FALSE: bool = true;
+
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        let none = if T::HAS_FALSE {
+            unsafe { sys::REEXPORTS_SCM_UNSPECIFIED }
+        } else {
+            unsafe { sys::REEXPORTS_SCM_BOOL_F }
+        };
+        if scm.0 == none {
+            Ok(None)
+        } else {
+            T::from_scm(api, scm).map(Some)
+        }
+    }
+}
+
+impl<A, B> IntoScm for (A, B)
+where
+    A: IntoScm,
+    B: IntoScm,
+{
+    fn into_scm(self, api: &Api) -> Scm {
+        Scm(unsafe { sys::scm_cons(self.0.into_scm(api).0, self.1.into_scm(api).0) })
+    }
+}
+impl<A, B> FromScm for (A, B)
+where
+    A: FromScm,
+    B: FromScm,
+{
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        if unsafe { sys::scm_pair_p(scm.0) } == unsafe { sys::REEXPORTS_SCM_BOOL_F } {
+            Err(GuileError::wrong_type_arg(api, "pair", scm))
+        } else {
+            A::from_scm(api, Scm(unsafe { sys::scm_car(scm.0) })).and_then(|car| {
+                B::from_scm(api, Scm(unsafe { sys::scm_cdr(scm.0) })).map(|cdr| (car, cdr))
+            })
+        }
+    }
+}
+
+/// Maps are converted into `equal?` hash tables.
+impl<K, V, S> IntoScm for HashMap<K, V, S>
+where
+    K: IntoScm,
+    V: IntoScm,
+{
+    fn into_scm(self, api: &Api) -> Scm {
+        let table =
+            unsafe { sys::scm_c_make_hash_table(self.len().try_into().unwrap_or_default()) };
+        self.into_iter().for_each(|(key, value)| unsafe {
+            sys::scm_hash_set_x(table, key.into_scm(api).0, value.into_scm(api).0);
+        });
+
+        Scm(table)
+    }
+}
+/// Maps can be extracted from both hash tables and association lists.
+impl<K, V, S> FromScm for HashMap<K, V, S>
+where
+    K: Eq + FromScm + Hash,
+    V: FromScm,
+    S: Default + std::hash::BuildHasher,
+{
+    fn from_scm(api: &Api, scm: Scm) -> Result<Self, GuileError> {
+        let alist =
+            if unsafe { sys::scm_hash_table_p(scm.0) } == unsafe { sys::REEXPORTS_SCM_BOOL_F } {
+                scm
+            } else {
+                Scm(unsafe {
+                    sys::scm_hash_map_to_list(
+                        sys::scm_c_public_ref(c"guile".as_ptr(), c"cons".as_ptr()),
+                        scm.0,
+                    )
+                })
+            };
+
+        api.list_to_vec(alist)
+            .ok_or_else(|| GuileError::wrong_type_arg(api, "hash table or association list", scm))?
+            .into_iter()
+            .map(|entry| <(K, V)>::from_scm(api, Scm(entry)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{guile, tests::ENV_VAR_LOCK},
+        std::fmt::Debug,
+    };
+
+    fn round_trip<T>(api: &Api, value: T)
+    where
+        T: Clone + Debug + FromScm + IntoScm + PartialEq,
+    {
+        assert_eq!(
+            T::from_scm(api, value.clone().into_scm(api)).unwrap(),
+            value
+        );
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn round_trips() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            round_trip(api, i8::MIN);
+            round_trip(api, u64::MAX);
+            round_trip(api, -1_isize);
+            round_trip(api, 0.5_f64);
+            round_trip(api, true);
+            round_trip(api, false);
+            round_trip(api, "foo".to_string());
+            round_trip(api, PathBuf::from("/foo/bar"));
+            round_trip(api, Bytevector(vec![0, 1, 255]));
+            round_trip(api, vec![1_u8, 2, 3]);
+            round_trip(api, Vector(vec!["foo".to_string(), "bar".to_string()]));
+            round_trip(api, Some(1_u32));
+            round_trip(api, None::<u32>);
+            round_trip(api, Some(false));
+            round_trip(api, Some(true));
+            round_trip(api, None::<bool>);
+            round_trip(api, Some(None::<u32>));
+            round_trip(api, None::<Option<u32>>);
+            round_trip(api, (1_i32, "foo".to_string()));
+            round_trip(api, HashMap::from([(1_u8, true), (2, false)]));
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn conversion_errors() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let error = u8::from_scm(api, 256_u16.into_scm(api)).unwrap_err();
+            assert_eq!(error.key(), "out-of-range");
+            let error = u8::from_scm(api, (-1_i8).into_scm(api)).unwrap_err();
+            assert_eq!(error.key(), "out-of-range");
+
+            let error = i32::from_scm(api, "foo".into_scm(api)).unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+            assert!(error.message().contains("exact integer"));
+            assert_eq!(
+                api.write_to_string(error.irritants()[0].get(api)),
+                "\"foo\""
+            );
+
+            let error = Vec::<u8>::from_scm(api, api.eval_cstring(c"'(1 . 2)")).unwrap_err();
+            assert!(error.message().contains("list"));
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn alists() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            assert_eq!(
+                HashMap::<String, u8>::from_scm(api, api.eval_cstring(c"'((\"foo\" . 1))"))
+                    .unwrap(),
+                HashMap::from([("foo".to_string(), 1)]),
+            );
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn keywords() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let [from, fade] = keyword_arguments(
+                api,
+                api.eval_cstring(c"'(#:fade 1 #:fade 2)"),
+                ["from", "fade"],
+            )
+            .unwrap();
+            assert!(from.is_none());
+            assert_eq!(u8::from_scm(api, fade.unwrap()).unwrap(), 2);
+
+            [
+                (
+                    c"'(#:volume 1)",
+                    "Unrecognized keyword (expected one of `#:from`, `#:fade`): #:volume",
+                ),
+                (c"'(#:from)", "has no value: #:from"),
+                (c"'(1 2)", "Invalid keyword: 1"),
+            ]
+            .into_iter()
+            .for_each(|(rest, message)| {
+                let error =
+                    keyword_arguments(api, api.eval_cstring(rest), ["from", "fade"]).unwrap_err();
+                assert_eq!(error.key(), "keyword-argument-error");
+                assert!(error.message().contains(message));
+            });
+        });
+    }
+}
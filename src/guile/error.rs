@@ -0,0 +1,436 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Errors thrown by scheme code.
+
+use {
+    crate::guile::{Api, Scm, convert::IntoScm, protected::ProtectedScm, sys},
+    std::{
+        any::Any,
+        borrow::Cow,
+        error::Error,
+        ffi::CStr,
+        fmt::{self, Display, Formatter},
+    },
+};
+
+/// The key that an exception is thrown to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ErrorKey {
+    /// `wrong-type-arg`
+    WrongTypeArg,
+    /// `out-of-range`
+    OutOfRange,
+    /// `misc-error`
+    #[default]
+    MiscError,
+    /// Any other symbol.
+    Custom(Cow<'static, str>),
+}
+impl ErrorKey {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::WrongTypeArg => "wrong-type-arg",
+            Self::OutOfRange => "out-of-range",
+            Self::MiscError => "misc-error",
+            Self::Custom(key) => key,
+        }
+    }
+}
+impl From<String> for ErrorKey {
+    fn from(key: String) -> Self {
+        match key.as_str() {
+            "wrong-type-arg" => Self::WrongTypeArg,
+            "out-of-range" => Self::OutOfRange,
+            "misc-error" => Self::MiscError,
+            _ => Self::Custom(Cow::Owned(key)),
+        }
+    }
+}
+impl From<&'static str> for ErrorKey {
+    fn from(key: &'static str) -> Self {
+        match key {
+            "wrong-type-arg" => Self::WrongTypeArg,
+            "out-of-range" => Self::OutOfRange,
+            "misc-error" => Self::MiscError,
+            _ => Self::Custom(Cow::Borrowed(key)),
+        }
+    }
+}
+impl Display for ErrorKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A scheme exception that was caught on the rust side, or that will be raised from it.
+///
+/// Irritants are protected from garbage collection and every other field is copied out of the scheme heap, so errors can outlive guile mode.
+#[derive(Clone, Debug, Default)]
+pub struct GuileError(Box<Inner>);
+#[derive(Clone, Debug, Default)]
+struct Inner {
+    key: ErrorKey,
+    subr: Option<String>,
+    message: String,
+    /// The unformatted message passed to `scm-error`, which consumes every irritant.
+    format: String,
+    irritants: Vec<ProtectedScm>,
+    backtrace: Option<String>,
+}
+impl GuileError {
+    /// Convert the arguments of a `catch` handler into an error.
+    pub(super) fn from_throw(api: &Api, key: Scm, args: Scm) -> Self {
+        let protect_all = |items: Vec<sys::SCM>| {
+            items
+                .into_iter()
+                .map(|item| ProtectedScm::new(api, Scm(item)))
+                .collect::<Vec<_>>()
+        };
+
+        match api.list_to_vec(args).as_deref() {
+            // Exceptions thrown through `scm-error` follow the `(subr message args rest)` convention.
+            Some(&[subr, message, irritants, rest])
+                if unsafe { sys::scm_is_string(message) } != 0
+                    && (irritants == unsafe { sys::REEXPORTS_SCM_BOOL_F }
+                        || api.list_to_vec(Scm(irritants)).is_some()) =>
+            {
+                Self(Box::new(Inner {
+                    key: ErrorKey::from(api.symbol_name(key)),
+                    subr: if unsafe { sys::scm_is_string(subr) } != 0 {
+                        Some(api.to_utf8(Scm(subr)))
+                    } else if unsafe { sys::scm_is_symbol(subr) } != 0 {
+                        Some(api.symbol_name(Scm(subr)))
+                    } else {
+                        None
+                    },
+                    // Rendering without a subr leaves only the formatted message.
+                    message: api.render_exception(
+                        key,
+                        Scm(unsafe {
+                            sys::scm_list_4(sys::REEXPORTS_SCM_BOOL_F, message, irritants, rest)
+                        }),
+                    ),
+                    format: api.to_utf8(Scm(message)),
+                    irritants: protect_all(api.list_to_vec(Scm(irritants)).unwrap_or_default()),
+                    backtrace: None,
+                }))
+            }
+            items => {
+                let key_name = api.symbol_name(key);
+                let irritants = protect_all(items.map(<[_]>::to_vec).unwrap_or_default());
+                Self(Box::new(Inner {
+                    subr: None,
+                    message: api.render_exception(key, args),
+                    // The same message that guile renders for throws that do not follow the convention.
+                    format: format!(
+                        "Throw to key `{}' with args `({})'.",
+                        escape(&key_name),
+                        vec!["~S"; irritants.len()].join(" "),
+                    ),
+                    irritants,
+                    key: ErrorKey::from(key_name),
+                    backtrace: None,
+                }))
+            }
+        }
+    }
+
+    /// Create an error that can be raised with [Api::raise].
+    ///
+    /// The message is not formatted, so it may contain tildes.
+    pub fn new<K, M>(key: K, message: M) -> Self
+    where
+        K: Into<ErrorKey>,
+        M: Into<String>,
+    {
+        let message = message.into();
+        Self(Box::new(Inner {
+            key: key.into(),
+            format: escape(&message),
+            message,
+            ..Default::default()
+        }))
+    }
+
+    /// Add an object that caused the error, which is written after the message like the irritants of `error`.
+    pub fn with_irritant<T>(mut self, api: &Api, irritant: T) -> Self
+    where
+        T: IntoScm,
+    {
+        let irritant = irritant.into_scm(api);
+        self.0.message = format!("{} {}", self.0.message, api.write_to_string(irritant));
+        self.0.format.push_str(" ~S");
+        self.0.irritants.push(ProtectedScm::new(api, irritant));
+        self
+    }
+
+    /// An error thrown to `wrong-type-arg` because `irritant` was not of the `expected` type.
+    pub fn wrong_type_arg(api: &Api, expected: &str, irritant: Scm) -> Self {
+        Self(Box::new(Inner {
+            key: ErrorKey::WrongTypeArg,
+            message: format!(
+                "Wrong type argument (expecting {expected}): {}",
+                api.write_to_string(irritant)
+            ),
+            format: format!("Wrong type argument (expecting {}): ~S", escape(expected)),
+            irritants: vec![ProtectedScm::new(api, irritant)],
+            ..Default::default()
+        }))
+    }
+
+    /// An error thrown to `out-of-range` because `irritant` does not fit into the requested type.
+    pub fn out_of_range(api: &Api, irritant: Scm) -> Self {
+        Self(Box::new(Inner {
+            key: ErrorKey::OutOfRange,
+            message: format!("Value out of range: {}", api.write_to_string(irritant)),
+            format: "Value out of range: ~S".to_string(),
+            irritants: vec![ProtectedScm::new(api, irritant)],
+            ..Default::default()
+        }))
+    }
+
+    /// An error thrown to `rust-panic` containing the panic message.
+    pub fn from_panic(subr: &CStr, payload: Box<dyn Any + Send>) -> Self {
+        let message = payload
+            .downcast::<String>()
+            .map(|message| *message)
+            .or_else(|payload| {
+                payload
+                    .downcast::<&str>()
+                    .map(|message| message.to_string())
+            })
+            .unwrap_or_else(|_| "Box<dyn Any>".to_string());
+        Self(Box::new(Inner {
+            key: ErrorKey::from("rust-panic"),
+            subr: Some(subr.to_string_lossy().into_owned()),
+            format: escape(&message),
+            message,
+            ..Default::default()
+        }))
+    }
+
+    /// Attach the name of the procedure that caused the error.
+    pub fn in_procedure(mut self, subr: &CStr) -> Self {
+        self.0.subr = Some(subr.to_string_lossy().into_owned());
+        self
+    }
+
+    /// Attach the name of the procedure, and the position and name of the argument that caused the error.
+    pub fn in_argument(mut self, subr: &CStr, position: usize, name: &str) -> Self {
+        self = self.in_procedure(subr);
+        self.0.message = format!("argument {position} (`{name}`): {}", self.0.message);
+        self.0.format = format!(
+            "argument {position} (`{}`): {}",
+            escape(name),
+            self.0.format
+        );
+        self
+    }
+
+    pub(super) fn with_backtrace(mut self, backtrace: Option<String>) -> Self {
+        self.0.backtrace = backtrace;
+        self
+    }
+
+    /// The name of the key the exception was thrown to, such as `wrong-type-arg`.
+    pub fn key(&self) -> &str {
+        self.0.key.as_str()
+    }
+
+    /// The name of the procedure that threw the exception if it is known.
+    pub fn subr(&self) -> Option<&str> {
+        self.0.subr.as_deref()
+    }
+
+    /// The message with every irritant formatted into it.
+    pub fn message(&self) -> &str {
+        &self.0.message
+    }
+
+    /// The objects that caused the error.
+    pub fn irritants(&self) -> &[ProtectedScm] {
+        &self.0.irritants
+    }
+
+    /// The stack at the point where the exception was thrown.
+    pub fn backtrace(&self) -> Option<&str> {
+        self.0.backtrace.as_deref()
+    }
+}
+impl Display for GuileError {
+    /// The alternate flag appends the backtrace.
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        if let Some(subr) = &self.0.subr {
+            write!(f, "In procedure {subr}: ")?;
+        }
+        f.write_str(&self.0.message)?;
+
+        match &self.0.backtrace {
+            Some(backtrace) if f.alternate() => write!(f, "\n{backtrace}"),
+            _ => Ok(()),
+        }
+    }
+}
+impl Error for GuileError {}
+
+/// Escape `message` so that formatting it leaves it unchanged.
+fn escape(message: &str) -> String {
+    message.replace('~', "~~")
+}
+
+impl Api {
+    /// Throw `error` to the nearest scheme `catch`.
+    ///
+    /// Every field of `error` is copied onto the scheme heap and dropped before throwing.
+    /// Guile functions should return [Result] instead, which raises after every rust value in the function was dropped.
+    ///
+    /// # Safety
+    ///
+    /// Throwing does not run destructors, so no value with a destructor may be alive in a rust frame between here and the nearest `catch`.
+    pub unsafe fn raise(&mut self, error: GuileError) -> ! {
+        let (key, subr, format, irritants) = {
+            let Inner {
+                key,
+                subr,
+                format,
+                irritants,
+                ..
+            } = *error.0;
+
+            (
+                self.make_symbol(key.as_str()),
+                subr.map_or(self.make_false(), |subr| self.make_string(&subr)),
+                self.make_string(&format),
+                irritants.iter().collect::<Vec<_>>().into_scm(self),
+            )
+        };
+
+        unsafe {
+            sys::scm_error_scm(
+                key.0,
+                subr.0,
+                format.0,
+                irritants.0,
+                sys::REEXPORTS_SCM_BOOL_F,
+            )
+        }
+    }
+
+    /// Render an exception the same way that guile does for uncaught exceptions.
+    fn render_exception(&self, Scm(key): Scm, Scm(args): Scm) -> String {
+        let port = unsafe { sys::scm_open_output_string() };
+        unsafe {
+            sys::scm_call_4(
+                sys::scm_c_public_ref(c"guile".as_ptr(), c"print-exception".as_ptr()),
+                port,
+                sys::REEXPORTS_SCM_BOOL_F,
+                key,
+                args,
+            );
+        }
+
+        let mut rendered = self.to_utf8(Scm(unsafe { sys::scm_get_output_string(port) }));
+        rendered.truncate(rendered.trim_end().len());
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::GuileError,
+        crate::{guile, tests::ENV_VAR_LOCK},
+    };
+
+    fn written(error: &GuileError) -> Vec<String> {
+        guile::with_guile(|api| {
+            error
+                .irritants()
+                .iter()
+                .map(|irritant| api.write_to_string(irritant.get(api)))
+                .collect()
+        })
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn standard_errors() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let error = guile::with_guile(|api| api.try_eval_cstring(c"(car 1)")).unwrap_err();
+        assert_eq!(error.key(), "wrong-type-arg");
+        assert_eq!(error.subr(), Some("car"));
+        assert_eq!(written(&error), ["1"]);
+        assert!(error.message().contains("expecting pair"));
+        assert!(error.to_string().starts_with("In procedure car: "));
+        assert!(error.backtrace().is_some());
+
+        let error =
+            guile::with_guile(|api| api.try_eval_cstring(c"(error \"oops\" 'foo)")).unwrap_err();
+        assert_eq!(error.key(), "misc-error");
+        assert!(error.message().contains("oops"));
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn custom_throws() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let error =
+            guile::with_guile(|api| api.try_eval_cstring(c"(throw 'foo 1 \"bar\")")).unwrap_err();
+        assert_eq!(error.key(), "foo");
+        assert_eq!(error.subr(), None);
+        assert_eq!(written(&error), ["1", "\"bar\""]);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn reraise() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            [
+                c"(car 1)",
+                c"(throw 'foo 1 \"bar\")",
+                c"(error \"oops ~a\" 'foo)",
+            ]
+            .into_iter()
+            .for_each(|expr| {
+                let error = api.try_eval_cstring(expr).unwrap_err();
+                let irritants = written(&error);
+                // SAFETY: the clone is moved into guile before it raises
+                let reraised = unsafe { api.catch(|api| api.raise(error.clone())) }.unwrap_err();
+                assert_eq!(reraised.key(), error.key());
+                assert_eq!(reraised.subr(), error.subr());
+                assert_eq!(reraised.message(), error.message());
+                assert_eq!(written(&reraised), irritants);
+            });
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn no_error() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            assert!(api.try_eval_cstring(c"#t").unwrap().is_true());
+        });
+    }
+}
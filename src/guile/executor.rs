@@ -0,0 +1,389 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A long lived thread that owns guile, so other threads never enter guile mode themselves.
+//!
+//! Threads such as the audio or UI threads must never block on garbage collection or user code.
+//! Instead they send jobs to the [Executor] and wait on, or poll, the [Task] that is returned.
+//!
+//! Jobs are rust code like the body of a [guile_fn][crate::guile::guile_fn], so they only enter guile through functions that catch exceptions, such as [Api::try_eval_cstring] or [Scm::call][crate::guile::Scm::call].
+//! A throw that escaped a job would skip the destructors of everything the job holds.
+
+use {
+    crate::{
+        events::{self, Event},
+        guile::{self, Api, error::GuileError},
+    },
+    parking_lot::{Condvar, Mutex},
+    std::{
+        collections::VecDeque,
+        error::Error,
+        fmt::{self, Display, Formatter},
+        future::Future,
+        panic::{self, AssertUnwindSafe},
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll, Waker},
+        thread::{self, JoinHandle},
+    },
+};
+
+type Job = Box<dyn FnOnce(&mut Api) + Send>;
+
+/// Report an error from a job on `(current-error-port)`.
+fn report(api: &mut Api, error: &GuileError) {
+    api.warn(&format!("error in job: {error}"));
+}
+
+/// Run `job`, reporting any panic it raises instead of letting it escape.
+///
+/// Returns [None] if the job panicked.
+fn run_caught<F, T>(api: &mut Api, job: F) -> Option<T>
+where
+    F: FnOnce(&mut Api) -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(|| job(api)))
+        .map_err(|payload| report(api, &GuileError::from_panic(c"executor", payload)))
+        .ok()
+}
+
+/// Which queue a job waits in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Commands from the user interface, which always run before bulk jobs.
+    Ui,
+    /// Work that can wait, such as dispatching events to hooks.
+    Bulk,
+}
+
+#[derive(Default)]
+struct Queues {
+    ui: VecDeque<Job>,
+    bulk: VecDeque<Job>,
+    /// The number of [Executor] handles that are alive.
+    handles: usize,
+}
+
+#[derive(Default)]
+struct Shared {
+    queues: Mutex<Queues>,
+    ready: Condvar,
+}
+impl Shared {
+    /// Wait for the next job, or return [None] once every handle has been dropped and the queues are empty.
+    fn next(&self) -> Option<Job> {
+        let mut queues = self.queues.lock();
+        loop {
+            if let Some(job) = queues.ui.pop_front().or_else(|| queues.bulk.pop_front()) {
+                return Some(job);
+            }
+            if queues.handles == 0 {
+                return None;
+            }
+            self.ready.wait(&mut queues);
+        }
+    }
+}
+
+/// A handle for sending jobs to the executor thread.
+///
+/// The thread exits once every handle has been dropped and the remaining jobs have run.
+pub struct Executor(Arc<Shared>);
+impl Executor {
+    /// Spawn the executor thread, which stays in guile mode except while it waits for jobs.
+    pub fn spawn() -> (Self, JoinHandle<()>) {
+        let shared = Arc::new(Shared::default());
+        shared.queues.lock().handles = 1;
+
+        let handle = {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                guile::with_guile(|api| {
+                    // Waiting happens outside of guile mode so it never holds up garbage collection.
+                    while let Some(job) = api.without_guile(|| shared.next()) {
+                        job(api);
+                    }
+                })
+            })
+        };
+
+        (Self(shared), handle)
+    }
+
+    /// Run `job` on the executor thread, returning a task that completes with its output.
+    pub fn execute<F, T>(&self, priority: Priority, job: F) -> Task<T>
+    where
+        F: FnOnce(&mut Api) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = Arc::new(Slot::default());
+        let completer = Completer(Some(Arc::clone(&slot)));
+        // The completer stays outside of `run_caught`, so a job that panics still cancels its task.
+        self.push(
+            priority,
+            Box::new(move |api| {
+                if let Some(output) = run_caught(api, job) {
+                    completer.complete(output);
+                }
+            }),
+        );
+
+        Task(slot)
+    }
+
+    fn push(&self, priority: Priority, job: Job) {
+        let mut queues = self.0.queues.lock();
+        match priority {
+            Priority::Ui => queues.ui.push_back(job),
+            Priority::Bulk => queues.bulk.push_back(job),
+        }
+        self.0.ready.notify_one();
+    }
+
+    /// Run `job` on the executor thread without waiting for it, reporting the error it returns on `(current-error-port)`.
+    pub fn spawn_job<F>(&self, priority: Priority, job: F)
+    where
+        F: FnOnce(&mut Api) -> Result<(), GuileError> + Send + 'static,
+    {
+        self.push(
+            priority,
+            Box::new(move |api| {
+                if let Some(Err(error)) = run_caught(api, job) {
+                    report(api, &error);
+                }
+            }),
+        );
+    }
+
+    /// Run the hooks for `event` on the executor thread, after any pending UI commands.
+    pub fn send_event(&self, event: Event) {
+        self.spawn_job(Priority::Bulk, move |api| {
+            events::dispatch(api, &event);
+            Ok(())
+        });
+    }
+}
+impl Clone for Executor {
+    fn clone(&self) -> Self {
+        self.0.queues.lock().handles += 1;
+        Self(Arc::clone(&self.0))
+    }
+}
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.0.queues.lock().handles -= 1;
+        self.0.ready.notify_one();
+    }
+}
+
+/// A job that was dropped before it finished, because it panicked or the executor stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Canceled;
+impl Display for Canceled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str("the job was dropped before it finished")
+    }
+}
+impl Error for Canceled {}
+
+struct Slot<T> {
+    state: Mutex<SlotState<T>>,
+    ready: Condvar,
+}
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(SlotState {
+                output: None,
+                waker: None,
+            }),
+            ready: Condvar::new(),
+        }
+    }
+}
+struct SlotState<T> {
+    output: Option<Result<T, Canceled>>,
+    waker: Option<Waker>,
+}
+
+/// The sending half of a [Task], which cancels the task if it is dropped without completing it.
+struct Completer<T>(Option<Arc<Slot<T>>>);
+impl<T> Completer<T> {
+    fn complete(mut self, output: T) {
+        self.send(Ok(output));
+    }
+
+    fn send(&mut self, output: Result<T, Canceled>) {
+        if let Some(slot) = self.0.take() {
+            let mut state = slot.state.lock();
+            state.output = Some(output);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            slot.ready.notify_all();
+        }
+    }
+}
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.send(Err(Canceled));
+    }
+}
+
+/// The output of a job sent to the [Executor], which can be waited on or awaited.
+pub struct Task<T>(Arc<Slot<T>>);
+impl<T> Task<T> {
+    /// Block until the job has finished.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the job was dropped before it finished.
+    pub fn wait(self) -> Result<T, Canceled> {
+        let mut state = self.0.state.lock();
+        loop {
+            if let Some(output) = state.output.take() {
+                return output;
+            }
+            self.0.ready.wait(&mut state);
+        }
+    }
+
+    /// Take the output if the job has finished, without blocking.
+    pub fn try_take(&mut self) -> Option<Result<T, Canceled>> {
+        self.0.state.lock().output.take()
+    }
+}
+impl<T> Future for Task<T> {
+    type Output = Result<T, Canceled>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.state.lock();
+        match state.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                state.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{guile::convert::FromScm, tests::ENV_VAR_LOCK},
+        std::{pin::pin, sync::mpsc},
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn execute() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let (executor, handle) = Executor::spawn();
+        let task = executor.execute(Priority::Ui, |api| {
+            i32::from_scm(api, api.eval_cstring(c"(+ 1 2)")).unwrap()
+        });
+        assert_eq!(task.wait(), Ok(3));
+
+        let mut task = pin!(executor.execute(Priority::Bulk, |_| "done"));
+        let mut context = Context::from_waker(Waker::noop());
+        let output = loop {
+            match task.as_mut().poll(&mut context) {
+                Poll::Ready(output) => break output,
+                Poll::Pending => thread::yield_now(),
+            }
+        };
+        assert_eq!(output, Ok("done"));
+
+        drop(executor);
+        handle.join().unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn ui_before_bulk() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let (executor, handle) = Executor::spawn();
+        let (block, blocked) = mpsc::channel::<()>();
+        executor.spawn_job(Priority::Bulk, move |_| {
+            blocked.recv().unwrap();
+            Ok(())
+        });
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let push = |priority| {
+            let order = Arc::clone(&order);
+            executor.execute(priority, move |_| order.lock().push(priority))
+        };
+        let tasks = [
+            push(Priority::Bulk),
+            push(Priority::Ui),
+            push(Priority::Bulk),
+            push(Priority::Ui),
+        ];
+
+        block.send(()).unwrap();
+        tasks.into_iter().for_each(|task| task.wait().unwrap());
+        assert_eq!(
+            *order.lock(),
+            [Priority::Ui, Priority::Ui, Priority::Bulk, Priority::Bulk]
+        );
+
+        drop(executor);
+        handle.join().unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn failures() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let (executor, handle) = Executor::spawn();
+        let task = executor.execute(Priority::Ui, |_| -> () { panic!("boom") });
+        assert_eq!(task.wait(), Err(Canceled));
+        let task = executor.execute(Priority::Ui, |api| {
+            api.try_eval_cstring(c"(error \"boom\")").map(drop)
+        });
+        assert_eq!(task.wait().unwrap().unwrap_err().key(), "misc-error");
+        executor.spawn_job(Priority::Bulk, |api| {
+            api.try_eval_cstring(c"(error \"boom\")").map(drop)
+        });
+        assert_eq!(executor.execute(Priority::Bulk, |_| 1).wait(), Ok(1));
+
+        drop(executor);
+        handle.join().unwrap();
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn spawn_in_guile_mode() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let (executor, handle) = Executor::spawn();
+            let task = executor.execute(Priority::Ui, |_| 1);
+            assert_eq!(api.without_guile(|| task.wait()), Ok(1));
+
+            drop(executor);
+            api.without_guile(|| handle.join()).unwrap();
+        });
+    }
+}
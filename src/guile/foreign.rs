@@ -0,0 +1,256 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Rust values exposed to scheme as foreign objects.
+
+use {
+    crate::guile::{Api, Scm, error::GuileError, protected::ProtectedScm, sys},
+    std::{
+        ffi::{CStr, CString, c_char, c_void},
+        ptr,
+        sync::OnceLock,
+    },
+};
+
+pub use proc_macros::ForeignObject;
+
+/// Rust types that scheme code can hold as opaque objects.
+///
+/// Implement this with `#[derive(ForeignObject)]`, which also implements [IntoScm][crate::guile::convert::IntoScm].
+/// The scheme name defaults to the kebab case name of the type, and can be changed with `#[foreign_object(name = "...")]`.
+/// Types are exported from `(empl)` unless another module is given with `#[foreign_object(module = "...")]`.
+///
+/// Values may be finalized on any thread, and are shared between every scheme object that refers to them.
+pub trait ForeignObject: Send + Sync + Sized + 'static {
+    /// The name of the type, such as `track`, which is defined as `<track>` with the predicate `track?`.
+    const NAME: &'static CStr;
+    /// The module that the type and predicate are exported from, with the names separated by spaces.
+    const MODULE: &'static CStr;
+
+    fn foreign_type() -> &'static ForeignType;
+}
+
+/// Storage for the scheme type of a [ForeignObject], which is created the first time it is used.
+#[derive(Debug, Default)]
+pub struct ForeignType(OnceLock<ProtectedScm>);
+impl ForeignType {
+    pub const fn new() -> Self {
+        Self(OnceLock::new())
+    }
+}
+
+unsafe extern "C" fn finalize<T>(object: sys::SCM)
+where
+    T: ForeignObject,
+{
+    let value = unsafe { sys::scm_foreign_object_ref(object, 0) }.cast::<T>();
+    if !value.is_null() {
+        drop(unsafe { Box::from_raw(value) });
+    }
+}
+
+unsafe extern "C" fn predicate<T>(object: sys::SCM) -> sys::SCM
+where
+    T: ForeignObject,
+{
+    let api = unsafe { Api::new_unchecked() };
+    if api.is_foreign::<T>(Scm::new(object)) {
+        api.make_true().0
+    } else {
+        api.make_false().0
+    }
+}
+
+impl Api {
+    /// Get the scheme type of `T`.
+    pub fn foreign_type<T>(&self) -> Scm
+    where
+        T: ForeignObject,
+    {
+        T::foreign_type()
+            .0
+            .get_or_init(|| {
+                let name = self.make_symbol(&format!("<{}>", T::NAME.to_string_lossy()));
+                let slots = self.make_symbol("value");
+                ProtectedScm::new(
+                    self,
+                    Scm::new(unsafe {
+                        sys::scm_make_foreign_object_type(
+                            name.0,
+                            sys::scm_list_1(slots.0),
+                            Some(finalize::<T>),
+                        )
+                    }),
+                )
+            })
+            .get(self)
+    }
+
+    /// Define and export the type `<name>` and the predicate `name?` for `T` in its module.
+    pub fn define_foreign_type<T>(&self)
+    where
+        T: ForeignObject,
+    {
+        unsafe extern "C" fn define<T>(_: *mut c_void)
+        where
+            T: ForeignObject,
+        {
+            let api = unsafe { Api::new_unchecked() };
+            let name = T::NAME.to_string_lossy();
+            let [type_name, predicate_name] = [format!("<{name}>"), format!("{name}?")]
+                .map(|name| CString::new(name).expect("names come from a `CStr`"));
+
+            unsafe {
+                sys::scm_c_define(type_name.as_ptr(), api.foreign_type::<T>().0);
+                sys::scm_c_define_gsubr(
+                    predicate_name.as_ptr(),
+                    1,
+                    0,
+                    0,
+                    predicate::<T> as sys::scm_t_subr,
+                );
+                sys::scm_c_export(
+                    type_name.as_ptr(),
+                    predicate_name.as_ptr(),
+                    ptr::null::<c_char>(),
+                );
+            }
+        }
+
+        unsafe {
+            sys::scm_c_define_module(T::MODULE.as_ptr(), Some(define::<T>), ptr::null_mut());
+        }
+    }
+
+    /// Move `value` into a new scheme object, which drops it when it is garbage collected.
+    pub fn make_foreign<T>(&self, value: T) -> Scm
+    where
+        T: ForeignObject,
+    {
+        let foreign_type = self.foreign_type::<T>();
+        Scm::new(unsafe {
+            sys::scm_make_foreign_object_1(foreign_type.0, Box::into_raw(Box::new(value)).cast())
+        })
+    }
+
+    pub fn is_foreign<T>(&self, Scm(scm): Scm) -> bool
+    where
+        T: ForeignObject,
+    {
+        // Objects of other foreign types can never share a class with `T`, so comparing classes is enough.
+        ptr::eq(
+            unsafe { sys::scm_class_of(scm) },
+            self.foreign_type::<T>().0,
+        )
+    }
+
+    /// Borrow the value inside a scheme object created by [Api::make_foreign].
+    ///
+    /// # Errors
+    ///
+    /// Objects of any other type produce a `wrong-type-arg` error.
+    ///
+    /// # Safety
+    ///
+    /// `scm` must stay reachable by the garbage collector for as long as the reference is used.
+    pub unsafe fn foreign_ref<'a, T>(&self, scm: Scm) -> Result<&'a T, GuileError>
+    where
+        T: ForeignObject,
+    {
+        if self.is_foreign::<T>(scm) {
+            Ok(unsafe { &*sys::scm_foreign_object_ref(scm.0, 0).cast::<T>() })
+        } else {
+            Err(GuileError::wrong_type_arg(
+                self,
+                &T::NAME.to_string_lossy(),
+                scm,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile::{self, convert::FromScm, guile_fn},
+            tests::ENV_VAR_LOCK,
+        },
+    };
+
+    #[derive(ForeignObject)]
+    #[foreign_object(name = "test-track", module = "empl test foreign")]
+    struct Track {
+        path: String,
+    }
+
+    #[derive(ForeignObject)]
+    struct PlayList;
+
+    #[guile_fn(module = "empl test foreign")]
+    fn make_test_track(_: &mut Api, path: String) -> Track {
+        Track { path }
+    }
+
+    #[guile_fn(module = "empl test foreign")]
+    fn test_track_path(_: &mut Api, track: &Track) -> String {
+        track.path.clone()
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn foreign_objects() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            assert_eq!(PlayList::NAME, c"play-list");
+
+            api.define_foreign_type::<Track>();
+            api.define_foreign_type::<PlayList>();
+            api.define_fn::<MakeTestTrack>();
+            api.define_fn::<TestTrackPath>();
+            api.eval_cstring(c"(use-modules (empl) (empl test foreign))");
+
+            assert_eq!(
+                String::from_scm(
+                    api,
+                    api.eval_cstring(c"(test-track-path (make-test-track \"foo.flac\"))")
+                )
+                .unwrap(),
+                "foo.flac"
+            );
+            assert!(
+                api.eval_cstring(c"(test-track? (make-test-track \"\"))")
+                    .is_true()
+            );
+            assert!(!api.eval_cstring(c"(test-track? 1)").is_true());
+            assert!(
+                !api.eval_cstring(c"(play-list? (make-test-track \"\"))")
+                    .is_true()
+            );
+            assert!(!api.is_foreign::<Track>(api.make_foreign(PlayList)));
+
+            let error = api
+                .try_eval_cstring(c"(test-track-path \"foo.flac\")")
+                .unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+            assert!(error.message().contains("argument 1 (`track`)"));
+        });
+    }
+}
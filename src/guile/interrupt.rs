@@ -0,0 +1,317 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Aborting evaluation that runs past its deadline, or that is interrupted with Ctrl-C.
+
+use {
+    crate::guile::{
+        Api, Scm,
+        convert::IntoScm,
+        error::GuileError,
+        executor::{Executor, Priority},
+        protected::ProtectedScm,
+        sys,
+    },
+    parking_lot::{Condvar, Mutex},
+    std::{
+        sync::{
+            Arc, LazyLock, Once,
+            atomic::{AtomicU8, Ordering},
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+const RUNNING: u8 = 0;
+const TIMED_OUT: u8 = 1;
+const INTERRUPTED: u8 = 2;
+const FINISHED: u8 = 3;
+
+/// An evaluation started with [Api::with_deadline].
+struct Guard {
+    deadline: Option<Instant>,
+    state: Arc<AtomicU8>,
+    /// The guile thread that is evaluating.
+    thread: ProtectedScm,
+    /// Raises the error for `state` when it is run as an async.
+    interrupt: ProtectedScm,
+}
+impl Guard {
+    /// Stop the guard with `state`, returning false if it has already stopped.
+    fn stop(&self, state: u8) -> bool {
+        self.state
+            .compare_exchange(RUNNING, state, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Raise the error in the guarded thread the next time it handles asyncs.
+    fn interrupt(&self, api: &Api) {
+        unsafe {
+            sys::scm_system_async_mark_for_thread(self.interrupt.get(api).0, self.thread.get(api).0)
+        };
+    }
+}
+
+/// Every evaluation that is currently guarded.
+static GUARDS: Mutex<Vec<Arc<Guard>>> = Mutex::new(Vec::new());
+/// Notified whenever a guard with a deadline is added.
+static WATCHDOG: Condvar = Condvar::new();
+static SPAWN_WATCHDOG: Once = Once::new();
+
+/// Delivers interrupts for the watchdog and the SIGINT thread, so neither of them enters guile itself.
+///
+/// This is not the executor that runs hooks and timers, since that may be busy with the very evaluation that is being interrupted.
+static INTERRUPTER: LazyLock<Executor> = LazyLock::new(|| Executor::spawn().0);
+
+/// Interrupt every one of `guards` from the [INTERRUPTER] without waiting for it.
+fn send_interrupts(guards: Vec<Arc<Guard>>) {
+    INTERRUPTER.spawn_job(Priority::Ui, move |api| {
+        guards.iter().for_each(|guard| guard.interrupt(api));
+        Ok(())
+    });
+}
+
+/// Interrupt every guard once its deadline has passed.
+fn watchdog() {
+    let mut guards = GUARDS.lock();
+    loop {
+        let now = Instant::now();
+        let expired = guards
+            .iter()
+            .filter(|guard| guard.deadline.is_some_and(|deadline| deadline <= now))
+            .filter(|guard| guard.stop(TIMED_OUT))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !expired.is_empty() {
+            send_interrupts(expired);
+            continue;
+        }
+
+        match guards
+            .iter()
+            .filter(|guard| guard.state.load(Ordering::Acquire) == RUNNING)
+            .filter_map(|guard| guard.deadline)
+            .min()
+        {
+            Some(deadline) => {
+                WATCHDOG.wait_until(&mut guards, deadline);
+            }
+            None => WATCHDOG.wait(&mut guards),
+        }
+    }
+}
+
+/// Interrupt every guarded evaluation, returning false if nothing was being evaluated.
+fn interrupt_all() -> bool {
+    let interrupted = GUARDS
+        .lock()
+        .iter()
+        .filter(|guard| guard.stop(INTERRUPTED))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if interrupted.is_empty() {
+        return false;
+    }
+
+    send_interrupts(interrupted);
+    true
+}
+
+impl Api {
+    /// Call `operation`, interrupting it with a `timeout` exception once `budget` has passed, or with an `interrupted` exception on SIGINT if [handle_sigint] was called.
+    ///
+    /// Interrupts are delivered the next time guile handles asyncs, so rust code that does not call into guile keeps running.
+    /// Like the body of a [guile_fn][crate::guile::guile_fn], `operation` must only evaluate scheme through functions that catch exceptions, such as [Api::try_eval_cstring] or [Scm::call], which return the interrupt along with the rest of its errors.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from `operation`.
+    pub fn with_deadline<F, T>(
+        &mut self,
+        budget: Option<Duration>,
+        operation: F,
+    ) -> Result<T, GuileError>
+    where
+        F: FnOnce(&mut Api) -> Result<T, GuileError>,
+    {
+        let state = Arc::new(AtomicU8::new(RUNNING));
+        let interrupt = {
+            let state = Arc::clone(&state);
+            self.make_procedure(move |api, _| match state.load(Ordering::Acquire) {
+                TIMED_OUT => Err(GuileError::new(
+                    "timeout",
+                    format!(
+                        "evaluation took longer than {} seconds",
+                        budget.unwrap_or_default().as_secs_f64()
+                    ),
+                )),
+                INTERRUPTED => Err(GuileError::new("interrupted", "evaluation was interrupted")),
+                _ => Ok(().into_scm(api)),
+            })
+        };
+        let guard = Arc::new(Guard {
+            deadline: budget.map(|budget| Instant::now() + budget),
+            state: Arc::clone(&state),
+            thread: ProtectedScm::new(self, Scm(unsafe { sys::scm_current_thread() })),
+            interrupt: ProtectedScm::new(self, interrupt),
+        });
+
+        if guard.deadline.is_some() {
+            SPAWN_WATCHDOG.call_once(|| drop(thread::spawn(watchdog)));
+        }
+        GUARDS.lock().push(Arc::clone(&guard));
+        WATCHDOG.notify_one();
+
+        let output = operation(self);
+
+        // An interrupt that is still queued does nothing once the guard has finished.
+        state.store(FINISHED, Ordering::Release);
+        GUARDS.lock().retain(|other| !Arc::ptr_eq(other, &guard));
+
+        output
+    }
+}
+
+#[cfg(unix)]
+mod sigint {
+    use {
+        super::interrupt_all,
+        std::{
+            io,
+            sync::atomic::{AtomicI32, Ordering},
+            thread,
+        },
+    };
+
+    /// The write end of the pipe that the SIGINT thread reads from, or `-1` if there is none.
+    static SIGINT_FD: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_sigint(_: libc::c_int) {
+        let fd = SIGINT_FD.load(Ordering::Relaxed);
+        let byte = 0u8;
+        // A full pipe already has an interrupt pending, so errors are ignored.
+        unsafe { libc::write(fd, (&raw const byte).cast(), 1) };
+    }
+
+    fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+        if result < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(result)
+        }
+    }
+
+    /// Make SIGINT interrupt every evaluation started with [Api::with_deadline][crate::guile::Api::with_deadline].
+    ///
+    /// When nothing is being evaluated, SIGINT terminates the process like it usually does.
+    ///
+    /// # Errors
+    ///
+    /// Fails if SIGINT is already handled, or if the signal handler could not be installed.
+    pub fn handle_sigint() -> io::Result<()> {
+        let mut fds = [-1; 2];
+        check(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+        let [read, write] = fds;
+        fds.iter().try_for_each(|&fd| {
+            check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) }).map(drop)
+        })?;
+        // The signal handler must never block.
+        check(unsafe { libc::fcntl(write, libc::F_SETFL, libc::O_NONBLOCK) })?;
+
+        SIGINT_FD
+            .compare_exchange(-1, write, Ordering::AcqRel, Ordering::Relaxed)
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::AlreadyExists, "SIGINT is already handled")
+            })?;
+
+        let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        action.sa_sigaction = on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        check(unsafe { libc::sigemptyset(&raw mut action.sa_mask) })?;
+        check(unsafe { libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut()) })?;
+
+        thread::spawn(move || {
+            let mut byte = 0u8;
+            loop {
+                match unsafe { libc::read(read, (&raw mut byte).cast(), 1) } {
+                    1 => {
+                        if !interrupt_all() {
+                            unsafe {
+                                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                                libc::raise(libc::SIGINT);
+                            }
+                        }
+                    }
+                    -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                    _ => return,
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+#[cfg(unix)]
+pub use sigint::handle_sigint;
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{guile, tests::ENV_VAR_LOCK},
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn timeout() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let error = api
+                .with_deadline(Some(Duration::from_millis(50)), |api| {
+                    api.try_eval_cstring(c"(let loop () (loop))")
+                })
+                .unwrap_err();
+            assert_eq!(error.key(), "timeout");
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn finished_before_deadline() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            assert!(
+                api.with_deadline(Some(Duration::from_millis(10)), |api| {
+                    api.try_eval_cstring(c"(+ 1 2)")
+                })
+                .is_ok()
+            );
+
+            // Nothing is thrown once the deadline passes.
+            thread::sleep(Duration::from_millis(50));
+            assert!(
+                api.try_eval_cstring(c"(let loop ((i 0)) (if (< i 100000) (loop (+ i 1)) i))")
+                    .is_ok()
+            );
+        });
+    }
+}
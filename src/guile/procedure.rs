@@ -0,0 +1,323 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Scheme procedures backed by rust closures, and calling scheme procedures from rust.
+
+use {
+    crate::guile::{
+        Api, Scm,
+        convert::{FromScm, IntoScm},
+        error::GuileError,
+        protected::ProtectedScm,
+        sys,
+    },
+    std::{
+        ffi::{CStr, c_void},
+        panic::{self, AssertUnwindSafe},
+        sync::OnceLock,
+    },
+};
+
+/// The closure behind a procedure created with [Api::make_procedure].
+pub type Closure = dyn Fn(&mut Api, &[Scm]) -> Result<Scm, GuileError> + Send + Sync;
+
+/// `(lambda (pointer) (lambda args (apply trampoline pointer args)))`, where `trampoline` calls the closure in `pointer`.
+static MAKE_PROCEDURE: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// The name used in errors raised from closures.
+const SUBR: &CStr = c"rust-closure";
+
+/// # Safety
+///
+/// `closure` must be a pointer created with [Api::make_procedure].
+unsafe extern "C" fn trampoline(closure: sys::SCM, args: sys::SCM) -> sys::SCM {
+    let output = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut api = unsafe { Api::new_unchecked() };
+        let closure = unsafe { &*sys::scm_to_pointer(closure).cast::<Box<Closure>>() };
+        let args = api
+            .list_to_vec(Scm(args))
+            .unwrap_or_default()
+            .into_iter()
+            .map(Scm)
+            .collect::<Vec<_>>();
+
+        closure(&mut api, &args)
+    }))
+    .unwrap_or_else(|payload| Err(GuileError::from_panic(SUBR, payload)));
+
+    // Every rust value has been dropped at this point, so raising cannot skip destructors.
+    match output {
+        Ok(Scm(output)) => output,
+        Err(error) if error.subr().is_some() => unsafe { Api::new_unchecked().raise(error) },
+        Err(error) => unsafe { Api::new_unchecked().raise(error.in_procedure(SUBR)) },
+    }
+}
+
+/// # Safety
+///
+/// `closure` must be a pointer created with [Api::make_procedure].
+unsafe extern "C" fn finalize(closure: *mut c_void) {
+    drop(unsafe { Box::from_raw(closure.cast::<Box<Closure>>()) });
+}
+
+impl Api {
+    /// Create a procedure that calls `closure` with its arguments.
+    ///
+    /// The closure is dropped once the procedure is garbage collected, which may happen on any thread.
+    pub fn make_procedure<F>(&self, closure: F) -> Scm
+    where
+        F: Fn(&mut Api, &[Scm]) -> Result<Scm, GuileError> + Send + Sync + 'static,
+    {
+        let make_procedure = MAKE_PROCEDURE
+            .get_or_init(|| {
+                let trampoline = unsafe {
+                    sys::scm_c_make_gsubr(SUBR.as_ptr(), 1, 0, 1, trampoline as sys::scm_t_subr)
+                };
+                let make_procedure = unsafe {
+                    sys::scm_eval_string_in_module(
+                        self.make_string(
+                            "(lambda (trampoline)
+                               (lambda (pointer)
+                                 (lambda args (apply trampoline pointer args))))",
+                        )
+                        .0,
+                        sys::scm_c_resolve_module(c"guile".as_ptr()),
+                    )
+                };
+                ProtectedScm::new(
+                    self,
+                    Scm(unsafe { sys::scm_call_1(make_procedure, trampoline) }),
+                )
+            })
+            .get(self);
+
+        let closure = Box::into_raw(Box::new(Box::new(closure) as Box<Closure>));
+        Scm(unsafe {
+            sys::scm_call_1(
+                make_procedure.0,
+                sys::scm_from_pointer(closure.cast(), Some(finalize)),
+            )
+        })
+    }
+}
+
+impl Api {
+    /// Get the value of the variable `name` in `module`, whether it is exported or not.
+    ///
+    /// Modules are named with their names separated by spaces, such as `empl player`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `module` does not exist.
+    pub fn lookup(&mut self, module: &CStr, name: &CStr) -> Result<Option<Scm>, GuileError> {
+        // SAFETY: the operation only borrows the names
+        unsafe { self.catch(|_| Scm(sys::scm_c_private_variable(module.as_ptr(), name.as_ptr()))) }
+            .map(|variable| self.variable_value(variable.0))
+    }
+
+    /// Get the value of the variable `name` if it is exported from `module`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `module` does not exist.
+    pub fn lookup_public(&mut self, module: &CStr, name: &CStr) -> Result<Option<Scm>, GuileError> {
+        // SAFETY: see above
+        unsafe { self.catch(|_| Scm(sys::scm_c_public_variable(module.as_ptr(), name.as_ptr()))) }
+            .map(|variable| self.variable_value(variable.0))
+    }
+
+    /// The value of `variable`, which is `#f` if no variable was found.
+    fn variable_value(&self, variable: sys::SCM) -> Option<Scm> {
+        (variable != self.make_false().0
+            && Scm(unsafe { sys::scm_variable_bound_p(variable) }).is_true())
+        .then(|| Scm(unsafe { sys::scm_variable_ref(variable) }))
+    }
+}
+
+impl Scm {
+    pub fn is_procedure(&self) -> bool {
+        Scm(unsafe { sys::scm_procedure_p(self.0) }).is_true()
+    }
+
+    /// Apply this procedure to `args`.
+    ///
+    /// # Errors
+    ///
+    /// Objects that are not procedures produce a `wrong-type-arg` error, and the wrong number of arguments produce a `wrong-number-of-args` error.
+    /// Any exception thrown by the procedure is returned as well.
+    pub fn call(&self, api: &mut Api, args: &[Scm]) -> Result<Scm, GuileError> {
+        if !self.is_procedure() {
+            return Err(GuileError::wrong_type_arg(api, "procedure", *self));
+        }
+
+        // `procedure-minimum-arity` returns `(required optional rest?)`, or `#f` when the arity is unknown.
+        if let Some(&[required, optional, rest]) = api
+            .list_to_vec(Scm(unsafe { sys::scm_procedure_minimum_arity(self.0) }))
+            .as_deref()
+            && let (Ok(required), Ok(optional), Ok(rest)) = (
+                usize::from_scm(api, Scm(required)),
+                usize::from_scm(api, Scm(optional)),
+                bool::from_scm(api, Scm(rest)),
+            )
+            && (args.len() < required || (!rest && args.len() > required + optional))
+        {
+            let expected = match (optional, rest) {
+                (_, true) => format!("at least {required}"),
+                (0, false) => required.to_string(),
+                (optional, false) => format!("{required} to {}", required + optional),
+            };
+            return Err(GuileError::new(
+                "wrong-number-of-args",
+                format!(
+                    "Wrong number of arguments (expected {expected}, got {}):",
+                    args.len()
+                ),
+            )
+            .with_irritant(api, *self));
+        }
+
+        let args = args.to_vec().into_scm(api);
+        // SAFETY: the arguments were converted to a scheme list beforehand
+        unsafe { api.catch(|_| Scm(sys::scm_apply_0(self.0, args.0))) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile::{
+                self,
+                convert::{FromScm, IntoScm},
+            },
+            tests::ENV_VAR_LOCK,
+        },
+        std::sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn closures() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let counter = api.make_procedure({
+                let calls = Arc::clone(&calls);
+                move |api, args| {
+                    Ok((calls.fetch_add(args.len(), Ordering::SeqCst) + args.len()).into_scm(api))
+                }
+            });
+            let failing = api.make_procedure(|_, _| Err(GuileError::new("my-error", "failed")));
+            let named = api.make_procedure(|_, _| {
+                Err(GuileError::new("my-error", "failed").in_procedure(c"named"))
+            });
+
+            unsafe {
+                sys::scm_c_define(c"test-counter".as_ptr(), counter.0);
+                sys::scm_c_define(c"test-failing".as_ptr(), failing.0);
+                sys::scm_c_define(c"test-named".as_ptr(), named.0);
+            }
+
+            assert_eq!(
+                usize::from_scm(
+                    api,
+                    api.eval_cstring(c"(begin (test-counter 1 2) (test-counter 3))")
+                )
+                .unwrap(),
+                3
+            );
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+            let error = api.try_eval_cstring(c"(test-failing)").unwrap_err();
+            assert_eq!(error.key(), "my-error");
+            assert_eq!(error.subr(), Some("rust-closure"));
+
+            let error = api.try_eval_cstring(c"(test-named)").unwrap_err();
+            assert_eq!(error.subr(), Some("named"));
+        });
+    }
+
+    #[test]
+    fn finalize_drops_closure() {
+        let captured = Arc::new(());
+        let closure = {
+            let captured = Arc::clone(&captured);
+            Box::new(move |api: &mut Api, _: &[Scm]| {
+                let _ = &captured;
+                Ok(().into_scm(api))
+            }) as Box<Closure>
+        };
+
+        let pointer = Box::into_raw(Box::new(closure));
+        assert_eq!(Arc::strong_count(&captured), 2);
+        unsafe { finalize(pointer.cast()) };
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn calls() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let add = api.lookup(c"guile", c"+").unwrap().unwrap();
+            let args = [1, 2, 3].map(|arg| arg.into_scm(api));
+            let sum = add.call(api, &args).unwrap();
+            assert_eq!(i32::from_scm(api, sum).unwrap(), 6);
+
+            let cons = api.lookup_public(c"guile", c"cons").unwrap().unwrap();
+            let error = cons.call(api, &args).unwrap_err();
+            assert_eq!(error.key(), "wrong-number-of-args");
+            assert!(error.message().contains("expected 2, got 3"));
+
+            let error = args[0].call(api, &[]).unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+
+            let car = api.lookup(c"guile", c"car").unwrap().unwrap();
+            let error = car.call(api, &args[..1]).unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+
+            assert!(
+                api.lookup(c"guile", c"empl-undefined-variable")
+                    .unwrap()
+                    .is_none()
+            );
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn lookup_missing_module() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            let error = api
+                .lookup_public(c"empl undefined-module", c"car")
+                .unwrap_err();
+            assert_eq!(error.key(), "misc-error");
+
+            let error = api.lookup(c"empl undefined-module", c"car").unwrap_err();
+            assert_eq!(error.key(), "misc-error");
+        });
+    }
+}
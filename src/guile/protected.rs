@@ -0,0 +1,98 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Scheme objects owned by rust.
+
+use {
+    crate::guile::{self, Api, Scm, convert::IntoScm, sys},
+    std::{ffi::CStr, sync::OnceLock},
+};
+
+/// A scheme object that is protected from garbage collection until it is dropped.
+///
+/// Unlike [Scm], this can be stored anywhere on the rust side, and sent to other threads.
+#[derive(Debug)]
+pub struct ProtectedScm(sys::SCM);
+// SAFETY: the object can only be accessed with an [Api], which cannot leave guile mode.
+unsafe impl Send for ProtectedScm {}
+// SAFETY: see above
+unsafe impl Sync for ProtectedScm {}
+impl ProtectedScm {
+    pub fn new(_: &Api, Scm(scm): Scm) -> Self {
+        unsafe { sys::scm_gc_protect_object(scm) };
+        Self(scm)
+    }
+
+    pub const fn get(&self, _: &Api) -> Scm {
+        Scm(self.0)
+    }
+}
+impl Clone for ProtectedScm {
+    fn clone(&self) -> Self {
+        guile::with_guile(|api| Self::new(api, Scm(self.0)))
+    }
+}
+impl Drop for ProtectedScm {
+    fn drop(&mut self) {
+        // Objects are protected once for every call to `scm_gc_protect_object`, so this never unprotects clones.
+        guile::with_guile(|_| unsafe { sys::scm_gc_unprotect_object(self.0) });
+    }
+}
+impl IntoScm for &ProtectedScm {
+    fn into_scm(self, api: &Api) -> Scm {
+        self.get(api)
+    }
+}
+
+impl Api {
+    /// Evaluate `source` the first time `cell` is used, and return the same object from then on.
+    pub fn eval_once(&self, cell: &OnceLock<ProtectedScm>, source: &CStr) -> Scm {
+        cell.get_or_init(|| ProtectedScm::new(self, self.eval_cstring(source)))
+            .get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{guile::convert::FromScm, tests::ENV_VAR_LOCK},
+        std::thread,
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn survives_gc() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let protected = guile::with_guile(|api| {
+            ProtectedScm::new(api, api.eval_cstring(c"(string-append \"foo\" \"bar\")"))
+        });
+        let clone = protected.clone();
+        drop(protected);
+
+        thread::spawn(move || {
+            guile::with_guile(|api| {
+                unsafe { sys::scm_gc() };
+                assert_eq!(String::from_scm(api, clone.get(api)).unwrap(), "foobar");
+            })
+        })
+        .join()
+        .unwrap();
+    }
+}
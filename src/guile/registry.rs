@@ -0,0 +1,340 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Registry of every function defined with [guile_fn][crate::guile::guile_fn].
+
+use {
+    crate::guile::{Api, GuileFn},
+    std::{
+        ffi::CStr,
+        fmt::{self, Display, Formatter, Write as _},
+        io::{self, Write},
+    },
+};
+
+/// A function submitted to the registry by [guile_fn][crate::guile::guile_fn].
+#[derive(Debug)]
+pub struct Binding {
+    name: &'static CStr,
+    module: &'static CStr,
+    required: usize,
+    optional: usize,
+    rest: bool,
+    keywords: &'static [&'static str],
+    /// The names of the parameters in the order they are passed.
+    params: &'static [&'static str],
+    doc: Option<&'static str>,
+    define: fn(&Api),
+}
+impl Binding {
+    pub const fn new<F>(params: &'static [&'static str]) -> Self
+    where
+        F: GuileFn,
+    {
+        Self {
+            name: F::NAME,
+            module: F::MODULE,
+            required: F::REQUIRED,
+            optional: F::OPTIONAL,
+            rest: F::REST,
+            keywords: F::KEYWORDS,
+            params,
+            doc: F::DOC,
+            define: Api::define_fn::<F>,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name.to_str().unwrap_or("<non utf8 name>")
+    }
+
+    /// The module with its names separated by spaces, such as `empl player`.
+    pub fn module(&self) -> &'static str {
+        self.module.to_str().unwrap_or("<non utf8 module>")
+    }
+
+    pub const fn doc(&self) -> Option<&'static str> {
+        self.doc
+    }
+
+    /// The signature in the style of the guile manual, such as `(play track #:key from fade)`.
+    pub const fn signature(&self) -> Signature<'_> {
+        Signature(self)
+    }
+}
+
+inventory::collect!(Binding);
+
+/// Every registered binding, sorted by module and then by name.
+pub fn bindings() -> Vec<&'static Binding> {
+    let mut bindings = inventory::iter::<Binding>.into_iter().collect::<Vec<_>>();
+    bindings.sort_by_key(|binding| (binding.module, binding.name));
+    bindings
+}
+
+/// The modules of every registered binding, with their names separated by spaces.
+pub fn modules() -> Vec<&'static CStr> {
+    let mut modules = inventory::iter::<Binding>
+        .into_iter()
+        .map(|binding| binding.module)
+        .collect::<Vec<_>>();
+    modules.sort();
+    modules.dedup();
+    modules
+}
+
+impl Api {
+    /// Define every registered binding in its module.
+    pub fn define_bindings(&self) {
+        inventory::iter::<Binding>
+            .into_iter()
+            .for_each(|binding| (binding.define)(self));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Signature<'a>(&'a Binding);
+impl Display for Signature<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        let Binding {
+            required,
+            optional,
+            rest,
+            keywords,
+            params,
+            ..
+        } = self.0;
+
+        write!(f, "({}", self.0.name())?;
+        params.iter().enumerate().try_for_each(|(i, param)| {
+            if i == *required && *optional != 0 {
+                f.write_str(" #:optional")?;
+            }
+            if i == required + optional {
+                if !keywords.is_empty() {
+                    f.write_str(" #:key")?;
+                } else if *rest {
+                    f.write_str(" .")?;
+                }
+            }
+            write!(f, " {param}")
+        })?;
+        f.write_char(')')
+    }
+}
+
+/// Output formats for [write].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Texinfo,
+    Json,
+}
+impl Format {
+    pub const NAMES: &[(&str, Self)] = &[
+        ("markdown", Self::Markdown),
+        ("texinfo", Self::Texinfo),
+        ("json", Self::Json),
+    ];
+
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(format, _)| format.as_bytes() == name)
+            .map(|(_, format)| *format)
+    }
+}
+
+/// Write a reference for `bindings`, grouped by module.
+pub fn write<W>(format: Format, bindings: &[&Binding], output: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let mut modules = bindings.chunk_by(|l, r| l.module == r.module);
+
+    match format {
+        Format::Markdown => {
+            writeln!(output, "# Scheme API")?;
+            modules.try_for_each(|bindings| {
+                writeln!(output, "\n## ({})", bindings[0].module())?;
+                bindings.iter().try_for_each(|binding| {
+                    writeln!(output, "\n### `{}`", binding.signature())?;
+                    binding
+                        .doc()
+                        .map(|doc| writeln!(output, "\n{doc}"))
+                        .unwrap_or(Ok(()))
+                })
+            })
+        }
+        Format::Texinfo => modules.try_for_each(|bindings| {
+            let module = texinfo_escape(bindings[0].module());
+            writeln!(output, "@node ({module})\n@section ({module})")?;
+            bindings.iter().try_for_each(|binding| {
+                let signature = binding.signature().to_string();
+                let (name, params) = signature[1..signature.len() - 1]
+                    .split_once(' ')
+                    .unwrap_or((&signature[1..signature.len() - 1], ""));
+                writeln!(
+                    output,
+                    "\n@deffn {{Scheme Procedure}} {} {}",
+                    texinfo_escape(name),
+                    texinfo_escape(params)
+                )?;
+                if let Some(doc) = binding.doc() {
+                    writeln!(output, "{}", texinfo_escape(doc))?;
+                }
+                writeln!(output, "@end deffn")
+            })?;
+            writeln!(output)
+        }),
+        Format::Json => {
+            output.write_all(b"[")?;
+            bindings.iter().enumerate().try_for_each(|(i, binding)| {
+                write!(
+                    output,
+                    "{}\n  {{\"name\": {}, \"module\": {}, \"required\": {}, \"optional\": {}, \"rest\": {}, \"keywords\": [{}], \"signature\": {}, \"doc\": {}}}",
+                    if i == 0 { "" } else { "," },
+                    JsonString(binding.name()),
+                    JsonString(binding.module()),
+                    binding.required,
+                    binding.optional,
+                    binding.rest && binding.keywords.is_empty(),
+                    binding
+                        .keywords
+                        .iter()
+                        .map(|keyword| JsonString(keyword).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    JsonString(&binding.signature().to_string()),
+                    binding
+                        .doc()
+                        .map(|doc| JsonString(doc).to_string())
+                        .unwrap_or_else(|| "null".to_string()),
+                )
+            })?;
+            output.write_all(if bindings.is_empty() {
+                b"]\n"
+            } else {
+                b"\n]\n"
+            })
+        }
+    }
+}
+
+fn texinfo_escape(text: &str) -> String {
+    text.replace('@', "@@")
+        .replace('{', "@{")
+        .replace('}', "@}")
+}
+
+/// A string that is displayed as a quoted and escaped json string.
+struct JsonString<'a>(&'a str);
+impl Display for JsonString<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_char('"')?;
+        self.0.chars().try_for_each(|char| match char {
+            '"' => f.write_str("\\\""),
+            '\\' => f.write_str("\\\\"),
+            '\n' => f.write_str("\\n"),
+            '\r' => f.write_str("\\r"),
+            '\t' => f.write_str("\\t"),
+            char if char.is_control() => write!(f, "\\u{:04x}", u32::from(char)),
+            char => f.write_char(char),
+        })?;
+        f.write_char('"')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::guile::{Scm, convert::Rest, guile_fn},
+    };
+
+    /// Seek to `position`.
+    #[guile_fn(guile_ident = "seek!", module = "empl test registry")]
+    fn seek(_: &mut Api, _position: f64, _relative: Option<bool>, Rest(_flags): Rest<Scm>) {}
+
+    #[guile_fn(module = "empl test registry")]
+    fn raw(_: &mut Api, [input]: [Scm; 1], _: [Option<Scm>; 0], _: Scm) -> Scm {
+        input
+    }
+
+    #[guile_fn(module = "empl test registry")]
+    fn fade(_: &mut Api, _seconds: f64, #[key] _from: f64, #[key] _to: f64) {}
+
+    fn registered(name: &str) -> &'static Binding {
+        bindings()
+            .into_iter()
+            .find(|binding| binding.name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn registered_bindings() {
+        let seek = registered("seek!");
+        assert_eq!(seek.module(), "empl test registry");
+        assert_eq!(seek.doc(), Some("Seek to `position`."));
+        assert_eq!(
+            seek.signature().to_string(),
+            "(seek! position #:optional relative . flags)"
+        );
+
+        assert_eq!(
+            registered("raw").signature().to_string(),
+            "(raw arg1 . rest)"
+        );
+        assert_eq!(
+            registered("fade").signature().to_string(),
+            "(fade seconds #:key from to)"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let bindings = [registered("seek!"), registered("raw")];
+        let write = |format| {
+            let mut output = Vec::new();
+            write(format, &bindings, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let markdown = write(Format::Markdown);
+        assert!(markdown.contains("## (empl test registry)"));
+        assert!(
+            markdown.contains(
+                "### `(seek! position #:optional relative . flags)`\n\nSeek to `position`."
+            )
+        );
+
+        let texinfo = write(Format::Texinfo);
+        assert!(texinfo.contains("@deffn {Scheme Procedure} seek! position #:optional relative . flags\nSeek to `position`.\n@end deffn"));
+
+        let json = write(Format::Json);
+        assert!(json.contains(r#""name": "raw", "module": "empl test registry", "required": 1, "optional": 0, "rest": true, "keywords": [], "signature": "(raw arg1 . rest)", "doc": null"#));
+    }
+
+    #[test]
+    fn json_escapes() {
+        assert_eq!(
+            JsonString("\"a\\b\"\n\u{1}").to_string(),
+            r#""\"a\\b\"\n\u0001""#
+        );
+    }
+}
@@ -19,27 +19,50 @@
 #![cfg_attr(not(test), no_main)]
 
 use {
-    crate::cli::{
-        argv::Argv,
-        parser::{Config, ParseCliArgumentsError},
+    crate::{
+        cli::{
+            argv::Argv,
+            parser::{Config, ParseCliArgumentsError},
+        },
+        config::{
+            check::{self, Report},
+            entrypoint::{self, LoadConfigError},
+        },
+        guile::{Api, executor::Executor},
     },
     std::{
         convert::identity,
         ffi::{c_char, c_int},
         io,
+        path::Path,
+        thread::JoinHandle,
     },
 };
 
 pub mod cli;
 pub mod config;
 pub mod display;
+pub mod events;
 pub mod guile;
+pub mod prelude;
+pub mod repl;
+pub mod timers;
 #[cfg(test)]
 mod tests {
-    use std::sync::RwLock;
+    use std::{env, path::PathBuf, process, sync::RwLock};
 
     /// Lock used to signal that environment variables are being written to during tests.
     pub static ENV_VAR_LOCK: RwLock<()> = RwLock::new(());
+
+    /// Lock used to signal that global guile state, such as the hooks, the clock, the load path, or the dry run, is being replaced during tests.
+    ///
+    /// Tests that depend on that state hold it for reading.
+    pub static GUILE_STATE_LOCK: RwLock<()> = RwLock::new(());
+
+    /// A path in the temporary directory that is unique to this process, such as `/tmp/empl-1234-cache`.
+    pub fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("empl-{}-{name}", process::id()))
+    }
 }
 
 // SAFETY: Every c program has done this since the dawn of time.
@@ -57,5 +80,142 @@ extern "C" fn main(argc: c_int, argv: *const *const c_char) -> c_int {
         }
     })
     .and_then(|config| config.ok_or(exitcode::OK))
+    .and_then(|config| {
+        // Guile encodes file names with the locale's encoding, which is only the user's once it has been installed, like the `guile` executable does.
+        // SAFETY: no other threads have been spawned yet
+        #[cfg(unix)]
+        unsafe {
+            libc::setlocale(libc::LC_ALL, c"".as_ptr());
+        }
+        #[cfg(unix)]
+        if let Err(error) = guile::interrupt::handle_sigint() {
+            eprintln!("failed to handle SIGINT: {error}");
+        }
+
+        guile::with_guile(|api| {
+            if config.check() {
+                // SAFETY: no other threads have been spawned yet
+                return unsafe { check::run(api, &config) }
+                    .map_err(load_error_code)
+                    .and_then(print_report);
+            }
+
+            // SAFETY: no other threads have been spawned yet
+            unsafe { entrypoint::load(api, &config) }
+                .map_err(load_error_code)
+                .and_then(|_| {
+                    let background = Background::spawn();
+                    let output = match (config.repl_socket(), config.repl()) {
+                        (Some(path), repl) => serve_repl_socket(api, path, repl),
+                        (None, true) => run_repl(api),
+                        (None, false) => Ok(()),
+                    };
+                    background.join(api);
+                    output
+                })
+        })
+    })
     .map_or_else(identity, |_| exitcode::OK)
 }
+
+/// The threads that run alongside the REPL, which all send their jobs to a single [Executor].
+struct Background {
+    executor: Executor,
+    executor_handle: JoinHandle<()>,
+    /// The config watcher, if it could be started.
+    #[cfg(unix)]
+    watcher: Option<JoinHandle<()>>,
+    timers: JoinHandle<()>,
+}
+impl Background {
+    fn spawn() -> Self {
+        let (executor, executor_handle) = Executor::spawn();
+        #[cfg(unix)]
+        let watcher = crate::config::reload::watcher::spawn(executor.clone())
+            .map_err(|error| eprintln!("failed to watch the config for changes: {error}"))
+            .ok();
+
+        Self {
+            #[cfg(unix)]
+            watcher,
+            timers: timers::spawn(executor.clone()),
+            executor,
+            executor_handle,
+        }
+    }
+
+    /// Stop every thread that sends jobs to the executor, then wait for the jobs that were already sent.
+    fn join(self, api: &mut Api) {
+        #[cfg(unix)]
+        crate::config::reload::watcher::stop();
+        timers::stop();
+        // A thread that panicked has already reported it.
+        api.without_guile(|| {
+            #[cfg(unix)]
+            if let Some(watcher) = self.watcher {
+                let _ = watcher.join();
+            }
+            let _ = self.timers.join();
+            drop(self.executor);
+            let _ = self.executor_handle.join();
+        });
+    }
+}
+
+/// Print `error` and pick the exit code for it.
+fn load_error_code(error: LoadConfigError) -> c_int {
+    eprintln!("{error}");
+    match error {
+        LoadConfigError::UnresolvedPath | LoadConfigError::MissingFile(_) => exitcode::NOINPUT,
+        LoadConfigError::UnreadableFile(..) => exitcode::IOERR,
+        LoadConfigError::NonUtf8Expr(..) => exitcode::DATAERR,
+        LoadConfigError::EvalFile(..) | LoadConfigError::EvalExpr(..) => exitcode::CONFIG,
+        LoadConfigError::Setup(_) => exitcode::SOFTWARE,
+    }
+}
+
+/// Print what the config would have done to stdout and its errors to stderr, failing if there were any errors.
+fn print_report(report: Report) -> Result<(), c_int> {
+    report
+        .effects
+        .iter()
+        .for_each(|effect| println!("{}: would {}", effect.origin, effect.message));
+    report.errors.iter().for_each(|error| eprintln!("{error}"));
+
+    if report.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(exitcode::CONFIG)
+    }
+}
+
+fn run_repl(api: &mut Api) -> Result<(), c_int> {
+    repl::run(api).map_err(|error| {
+        eprintln!("failed to start the repl: {error}");
+        exitcode::SOFTWARE
+    })
+}
+
+/// Serve REPLs on `path`, in the background if a local REPL was requested as well.
+#[cfg(unix)]
+fn serve_repl_socket(api: &mut Api, path: &Path, repl: bool) -> Result<(), c_int> {
+    repl::import_modules(api);
+    let served = if repl {
+        repl::server::spawn(api, path)
+    } else {
+        repl::server::serve(api, path)
+    };
+
+    served
+        .map_err(|error| {
+            eprintln!("failed to start the repl server: {error}");
+            exitcode::SOFTWARE
+        })
+        .and_then(|_| if repl { run_repl(api) } else { Ok(()) })
+}
+
+#[cfg(not(unix))]
+fn serve_repl_socket(_: &mut Api, _: &Path, _: bool) -> Result<(), c_int> {
+    eprintln!("`--repl-socket` is only supported on unix");
+    Err(exitcode::UNAVAILABLE)
+}
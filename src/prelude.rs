@@ -0,0 +1,114 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Scheme modules that are bundled with empl, such as `(empl prelude)`.
+//!
+//! The modules are defined before the config is loaded, so `use-modules` finds them without searching `%load-path`.
+//! Passing `--no-prelude` skips them, which helps when debugging a config.
+
+use {
+    crate::guile::{Api, convert::IntoScm, error::GuileError, protected::ProtectedScm},
+    std::{ffi::CStr, sync::OnceLock},
+};
+
+/// Every bundled module, as the file name used in error messages and its source, in the order they are defined.
+pub const MODULES: &[(&str, &str)] = &[("empl/prelude.scm", include_str!("prelude/prelude.scm"))];
+
+/// Evaluates every form in a string in order, starting from `(guile-user)`, with locations pointing into `file`.
+const DEFINE_MODULE_SOURCE: &CStr = c"
+(lambda (file source)
+  (call-with-input-string source
+    (lambda (port)
+      (set-port-filename! port file)
+      (save-module-excursion
+        (lambda ()
+          (set-current-module (resolve-module '(guile-user)))
+          (let loop ((form (read port)))
+            (unless (eof-object? form)
+              (primitive-eval form)
+              (loop (read port)))))))))";
+
+static DEFINE_MODULE: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// Define every bundled module.
+///
+/// This must happen after [Api::define_bindings], since the modules use the bindings.
+///
+/// # Errors
+///
+/// Fails if a module throws an exception while it is defined.
+pub fn load(api: &mut Api) -> Result<(), GuileError> {
+    MODULES.iter().try_for_each(|(file, source)| {
+        let args = [file.into_scm(api), source.into_scm(api)];
+        api.eval_once(&DEFINE_MODULE, DEFINE_MODULE_SOURCE)
+            .call(api, &args)
+            .map(drop)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            events::Event,
+            guile::{self, convert::FromScm},
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK},
+            timers,
+        },
+        std::time::Duration,
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn prelude() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        guile::with_guile(|api| {
+            timers::use_fake_clock();
+            api.define_bindings();
+            api.define_hooks();
+            load(api).unwrap();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl prelude) (empl hooks))
+                    (define calls '())
+                    (on seeked-hook (position) (set! calls (cons 'on calls)))
+                    (once seeked-hook (position) (set! calls (cons 'once calls)))
+                    (after 1 (set! calls (cons 'after calls))))",
+            );
+            let calls = |api: &mut Api| {
+                Vec::<String>::from_scm(
+                    api,
+                    api.eval_cstring(c"(reverse (map symbol->string calls))"),
+                )
+                .unwrap()
+            };
+
+            assert!(api.run_hooks(&Event::Seeked(Duration::ZERO)).is_empty());
+            assert!(api.run_hooks(&Event::Seeked(Duration::ZERO)).is_empty());
+            assert_eq!(calls(api), ["on", "once", "on"]);
+
+            assert!(timers::advance(api, Duration::from_secs(1)).is_empty());
+            assert_eq!(calls(api), ["on", "once", "on", "after"]);
+
+            api.eval_cstring(c"(reset-hook! seeked-hook)");
+        });
+    }
+}
@@ -0,0 +1,42 @@
+;; empl - Extensible Music PLayer
+;; Copyright (C) 2025  Andrew Chi
+
+;; This file is part of empl.
+
+;; empl is free software: you can redistribute it and/or modify
+;; it under the terms of the GNU General Public License as published by
+;; the Free Software Foundation, either version 3 of the License, or
+;; (at your option) any later version.
+
+;; empl is distributed in the hope that it will be useful,
+;; but WITHOUT ANY WARRANTY; without even the implied warranty of
+;; MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+;; GNU General Public License for more details.
+
+;; You should have received a copy of the GNU General Public License
+;; along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+;;; Convenience macros for configs, bundled with empl and loaded unless `--no-prelude` is given.
+
+(define-module (empl prelude)
+  #:use-module (empl timers)
+  #:export (on once after repeatedly))
+
+;; Call BODY with FORMALS bound to the arguments every time HOOK is run.
+(define-syntax-rule (on hook formals body ...)
+  (add-hook! hook (lambda formals body ...)))
+
+;; Call BODY with FORMALS bound to the arguments the next time HOOK is run only.
+(define-syntax-rule (once hook formals body ...)
+  (letrec ((handler (lambda formals
+                      (remove-hook! hook handler)
+                      body ...)))
+    (add-hook! hook handler)))
+
+;; Evaluate BODY once SECONDS have passed, returning a timer for `cancel-timer'.
+(define-syntax-rule (after seconds body ...)
+  (run-after seconds (lambda () body ...)))
+
+;; Evaluate BODY every SECONDS, returning a timer for `cancel-timer'.
+(define-syntax-rule (repeatedly seconds body ...)
+  (run-every seconds (lambda () body ...)))
@@ -26,6 +26,12 @@ _Bool reexports_scm_equal_p(SCM x, SCM y) {
   return scm_equal_p(x, y);
 }
 
+void *reexports_scm_bytevector_contents(SCM bv) {
+  return SCM_BYTEVECTOR_CONTENTS(bv);
+}
+
 const SCM REEXPORTS_SCM_BOOL_F = SCM_BOOL_F;
 const SCM REEXPORTS_SCM_BOOL_T = SCM_BOOL_T;
 const SCM REEXPORTS_SCM_UNDEFINED = SCM_UNDEFINED;
+const SCM REEXPORTS_SCM_EOL = SCM_EOL;
+const SCM REEXPORTS_SCM_UNSPECIFIED = SCM_UNSPECIFIED;
@@ -24,9 +24,12 @@
 #include <libguile.h>
 
 extern _Bool reexports_scm_equal_p(SCM, SCM);
+extern void *reexports_scm_bytevector_contents(SCM);
 
 extern const SCM REEXPORTS_SCM_BOOL_F;
 extern const SCM REEXPORTS_SCM_BOOL_T;
 extern const SCM REEXPORTS_SCM_UNDEFINED;
+extern const SCM REEXPORTS_SCM_EOL;
+extern const SCM REEXPORTS_SCM_UNSPECIFIED;
 
 #endif // REEXPORTS_H
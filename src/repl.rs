@@ -0,0 +1,132 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Interactive REPL started with `-r`.
+
+#[cfg(unix)]
+pub mod server;
+
+use {
+    crate::{
+        events,
+        guile::{Api, convert::IntoScm, error::GuileError, protected::ProtectedScm, registry},
+    },
+    std::{ffi::CStr, iter, sync::OnceLock},
+};
+
+/// Calls a thunk with `(guile-user)` as the current module, which is where `top-repl` evaluates expressions.
+const IN_USER_MODULE_SOURCE: &CStr = c"
+(lambda (thunk)
+  (save-module-excursion
+    (lambda ()
+      (set-current-module (resolve-module '(guile-user)))
+      (thunk))))";
+
+static IN_USER_MODULE: OnceLock<ProtectedScm> = OnceLock::new();
+
+/// Import every empl module into the current module.
+pub fn import_modules(api: &Api) {
+    registry::modules()
+        .into_iter()
+        .chain(iter::once(events::MODULE))
+        .for_each(|module| api.use_module(module));
+}
+
+/// Import every empl module into `(guile-user)`.
+fn import_into_user_module(api: &mut Api) -> Result<(), GuileError> {
+    let import = api.make_procedure(|api, _| {
+        import_modules(api);
+        Ok(().into_scm(api))
+    });
+    api.eval_once(&IN_USER_MODULE, IN_USER_MODULE_SOURCE)
+        .call(api, &[import])
+        .map(drop)
+}
+
+/// Import every empl module, then run the REPL from `(ice-9 top-repl)` until it exits.
+///
+/// # Errors
+///
+/// Fails if the REPL could not be started.
+pub fn run(api: &mut Api) -> Result<(), GuileError> {
+    import_into_user_module(api)?;
+    let result = api
+        .lookup_public(c"ice-9 top-repl", c"top-repl")?
+        .ok_or_else(|| GuileError::new("unbound-variable", "`top-repl` is not defined"))?
+        .call(api, &[]);
+    match result {
+        // `(exit)` and `,quit` throw to `quit`, which ends the REPL like the end of its input does.
+        Err(error) if error.key() == "quit" => Ok(()),
+        result => result.map(drop),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile::{self, guile_fn},
+            tests::ENV_VAR_LOCK,
+        },
+    };
+
+    #[guile_fn(module = "empl test repl")]
+    fn repl_test_binding(_: &mut Api) -> bool {
+        true
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn imports() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            api.define_bindings();
+            api.define_hooks();
+            // Switch to an empty module so imports from other tests are not visible.
+            api.eval_cstring(c"(define-module (empl test repl user))");
+            assert!(!api.eval_cstring(c"(defined? 'repl-test-binding)").is_true());
+
+            import_modules(api);
+            assert!(api.eval_cstring(c"(defined? 'shutdown-hook)").is_true());
+            assert!(api.eval_cstring(c"(repl-test-binding)").is_true());
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn imports_into_user_module() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        guile::with_guile(|api| {
+            api.define_bindings();
+            api.define_hooks();
+            api.eval_cstring(c"(define-module (empl test repl other))");
+
+            import_into_user_module(api).unwrap();
+            assert!(
+                api.eval_cstring(
+                    c"(module-defined? (resolve-module '(guile-user)) 'repl-test-binding)"
+                )
+                .is_true()
+            );
+            assert!(!api.eval_cstring(c"(defined? 'repl-test-binding)").is_true());
+        });
+    }
+}
@@ -0,0 +1,208 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! REPL server on a unix domain socket, started with `--repl-socket` or `(start-repl-server)`.
+
+use {
+    crate::{
+        config::{check, default_paths::DEFAULT_REPL_SOCKET},
+        guile::{Api, Scm, convert::IntoScm, error::GuileError, guile_fn},
+    },
+    std::{
+        fs::{self, DirBuilder, Permissions},
+        io,
+        os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Errors from the file system are thrown to `system-error` like guile's own procedures.
+fn system_error(message: &str, path: &Path, error: io::Error) -> GuileError {
+    GuileError::new(
+        "system-error",
+        format!("{message} `{}`: {error}", path.display()),
+    )
+}
+
+/// Create a socket at `path` that only the current user can connect to.
+///
+/// The parent directory is created with mode `0700` if it does not exist, and a socket left over at `path` is replaced.
+/// The permissions of the socket can only be changed after it is bound, so the parent directory must not be accessible to other users.
+fn make_socket(api: &mut Api, path: &Path) -> Result<Scm, GuileError> {
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(parent)
+        .map_err(|error| system_error("failed to create directory", parent, error))?;
+    let metadata =
+        fs::metadata(parent).map_err(|error| system_error("failed to read", parent, error))?;
+    if metadata.uid() != unsafe { libc::getuid() } || metadata.mode() & 0o077 != 0 {
+        return Err(system_error(
+            "refusing to create a socket in",
+            parent,
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the directory is accessible to other users",
+            ),
+        ));
+    }
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+            .map_err(|error| system_error("failed to remove old socket", path, error))?,
+        Ok(_) => {
+            return Err(system_error(
+                "refusing to replace",
+                path,
+                io::Error::from(io::ErrorKind::AlreadyExists),
+            ));
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(system_error("failed to read", path, error)),
+    }
+
+    let args = [api.make_keyword("path"), path.into_scm(api)];
+    let socket = api
+        .lookup_public(c"system repl server", c"make-unix-domain-server-socket")?
+        .ok_or_else(|| {
+            GuileError::new(
+                "unbound-variable",
+                "`make-unix-domain-server-socket` is not defined",
+            )
+        })?
+        .call(api, &args)?;
+
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+        .map_err(|error| system_error("failed to set the permissions of", path, error))?;
+
+    Ok(socket)
+}
+
+/// Serve REPLs on the socket at `path` from a new thread.
+///
+/// # Errors
+///
+/// Fails if the socket could not be created.
+pub fn spawn(api: &mut Api, path: &Path) -> Result<(), GuileError> {
+    run(api, path, c"spawn-server")
+}
+
+/// Serve REPLs on the socket at `path` until the server is stopped.
+///
+/// # Errors
+///
+/// Fails if the socket could not be created.
+pub fn serve(api: &mut Api, path: &Path) -> Result<(), GuileError> {
+    run(api, path, c"run-server")
+}
+
+fn run(api: &mut Api, path: &Path, procedure: &std::ffi::CStr) -> Result<(), GuileError> {
+    let socket = make_socket(api, path)?;
+    api.lookup_public(c"system repl server", procedure)?
+        .ok_or_else(|| GuileError::new("unbound-variable", "the repl server is not defined"))?
+        .call(api, &[socket])
+        .map(drop)
+}
+
+/// Start a REPL server on the unix socket at `path`, which defaults to
+/// `$XDG_RUNTIME_DIR/empl/repl.sock` (`$TMPDIR/empl/repl.sock` on macOS).
+///
+/// Only the current user can connect to the socket. Returns the path of the socket.
+#[guile_fn(module = "empl repl")]
+fn start_repl_server(api: &mut Api, path: Option<PathBuf>) -> Result<PathBuf, GuileError> {
+    let path = match path {
+        Some(path) => path,
+        // SAFETY: environment variables are never modified outside of tests.
+        None => unsafe { DEFAULT_REPL_SOCKET.to_path_buf() }.map_err(|error| {
+            GuileError::new(
+                "system-error",
+                format!("failed to resolve the default repl socket: {error}"),
+            )
+        })?,
+    };
+
+    if check::record_effect(|| format!("start a repl server on `{}`", path.display())) {
+        return Ok(path);
+    }
+
+    spawn(api, &path).map(|_| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile,
+            tests::{ENV_VAR_LOCK, temp_path},
+        },
+        std::os::unix::net::UnixStream,
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn socket_permissions() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let dir = temp_path("repl-server");
+        let path = dir.join("nested").join("repl.sock");
+
+        guile::with_guile(|api| {
+            spawn(api, &path).unwrap();
+            // Replacing a stale socket from an earlier run must work as well.
+            spawn(api, &path).unwrap();
+
+            let error = spawn(api, &dir.join("nested")).unwrap_err();
+            assert_eq!(error.key(), "system-error");
+
+            let shared = dir.join("shared");
+            fs::create_dir(&shared).unwrap();
+            fs::set_permissions(&shared, Permissions::from_mode(0o755)).unwrap();
+            let error = spawn(api, &shared.join("repl.sock")).unwrap_err();
+            assert_eq!(error.key(), "system-error");
+            assert!(!shared.join("repl.sock").exists());
+        });
+
+        assert!(UnixStream::connect(&path).is_ok());
+        guile::with_guile(|api| {
+            api.lookup_public(c"system repl server", c"stop-server-and-clients!")
+                .unwrap()
+                .unwrap()
+                .call(api, &[])
+                .unwrap();
+        });
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(
+            fs::metadata(dir.join("nested"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o700
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
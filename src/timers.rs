@@ -0,0 +1,545 @@
+// empl - Extensible Music PLayer
+// Copyright (C) 2025  Andrew Chi
+
+// This file is part of empl.
+
+// empl is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// empl is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with empl.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Timers that call scheme thunks later, exported from the `(empl timers)` module.
+//!
+//! Timers are kept in a hashed timer wheel, which is advanced by the thread started with [spawn].
+//! The thunks themselves are run on the [Executor], so they never run on the timer thread.
+
+use {
+    crate::{
+        config::check,
+        guile::{
+            Api, Scm,
+            convert::IntoScm,
+            error::GuileError,
+            executor::{Executor, Priority},
+            foreign::ForeignObject,
+            guile_fn,
+            protected::ProtectedScm,
+        },
+    },
+    parking_lot::{Condvar, Mutex},
+    std::{
+        mem,
+        sync::{
+            Arc, LazyLock,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+/// The resolution of every timer.
+const TICK: Duration = Duration::from_millis(10);
+/// The number of slots in the wheel, each of which holds the timers due on every `SLOTS`th tick.
+const SLOTS: usize = 512;
+
+static TIMERS: LazyLock<Mutex<Timers>> = LazyLock::new(|| {
+    Mutex::new(Timers {
+        clock: Clock::System(Instant::now()),
+        wheel: Wheel::new(),
+        next_id: 0,
+        generation: 0,
+        stopping: false,
+    })
+});
+
+/// Notified whenever a timer is scheduled or the clock changes, so the timer thread can pick a new deadline.
+static CHANGED: Condvar = Condvar::new();
+
+/// A timer created by `run-after` or `run-every`.
+#[derive(ForeignObject)]
+#[foreign_object(module = "empl timers")]
+pub struct Timer {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Clock {
+    /// Real time since the wheel was created.
+    System(Instant),
+    /// Time that only passes when [advance] is called.
+    Fake(Duration),
+}
+impl Clock {
+    fn elapsed(&self) -> Duration {
+        match self {
+            Self::System(start) => start.elapsed(),
+            Self::Fake(elapsed) => *elapsed,
+        }
+    }
+}
+
+struct Timers {
+    clock: Clock,
+    wheel: Wheel,
+    next_id: u64,
+    /// Bumped by [Api::replace_timers], so timers from the previous config can be told apart.
+    generation: u64,
+    /// Set by [stop] until the timer thread has exited.
+    stopping: bool,
+}
+impl Timers {
+    fn now(&self) -> u64 {
+        ticks(self.clock.elapsed())
+    }
+
+    fn schedule(
+        &mut self,
+        delay: Duration,
+        period: Option<Duration>,
+        thunk: ProtectedScm,
+    ) -> Timer {
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        self.wheel.insert(Entry {
+            id,
+            // Counting from the exact time rather than the current tick means timers never fire early.
+            due: ticks_ceil(self.clock.elapsed() + delay).max(self.wheel.tick + 1),
+            period: period.map(|period| ticks_ceil(period).max(1)),
+            generation: self.generation,
+            thunk: Arc::new(thunk),
+            cancelled: Arc::clone(&cancelled),
+        });
+        CHANGED.notify_all();
+
+        Timer { id, cancelled }
+    }
+
+    /// Advance the wheel to the current time, returning the timers that are due.
+    fn fire(&mut self) -> Vec<Fired> {
+        let now = self.now();
+        self.wheel.advance(now)
+    }
+}
+
+/// The number of whole ticks in `duration`.
+fn ticks(duration: Duration) -> u64 {
+    (duration.as_nanos() / TICK.as_nanos()) as u64
+}
+
+/// The number of ticks in `duration`, rounded up so timers never fire early.
+fn ticks_ceil(duration: Duration) -> u64 {
+    duration.as_nanos().div_ceil(TICK.as_nanos()) as u64
+}
+
+/// The time from the start of the clock until `ticks`.
+fn duration(ticks: u64) -> Duration {
+    TICK * u32::try_from(ticks).unwrap_or(u32::MAX)
+}
+
+struct Entry {
+    id: u64,
+    /// The tick that the timer fires on.
+    due: u64,
+    /// The number of ticks between firings of a repeating timer.
+    period: Option<u64>,
+    generation: u64,
+    thunk: Arc<ProtectedScm>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// A timer that was due, and whose thunk should be called.
+struct Fired {
+    thunk: Arc<ProtectedScm>,
+    cancelled: Arc<AtomicBool>,
+}
+impl Fired {
+    /// Call the thunk, unless the timer was cancelled after it fired.
+    fn run(self, api: &mut Api) -> Result<(), GuileError> {
+        if self.cancelled.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        self.thunk.get(api).call(api, &[]).map(drop)
+    }
+}
+
+struct Wheel {
+    /// The last tick that was advanced to.
+    tick: u64,
+    slots: Vec<Vec<Entry>>,
+}
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            tick: 0,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        self.slots[entry.due as usize % SLOTS].push(entry);
+    }
+
+    /// Advance to `to`, rescheduling repeating timers and returning every timer that was due in the order they were due.
+    fn advance(&mut self, to: u64) -> Vec<Fired> {
+        if to <= self.tick {
+            return Vec::new();
+        }
+
+        // Only the slots for the ticks that passed can hold due timers, unless the wheel went all the way around.
+        let slots = if to - self.tick >= SLOTS as u64 {
+            (0..SLOTS).collect::<Vec<_>>()
+        } else {
+            (self.tick + 1..=to)
+                .map(|tick| tick as usize % SLOTS)
+                .collect()
+        };
+        self.tick = to;
+
+        let mut due = slots
+            .into_iter()
+            .flat_map(|slot| {
+                let (due, pending) = mem::take(&mut self.slots[slot])
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|entry| entry.due <= to);
+                self.slots[slot] = pending;
+                due
+            })
+            .collect::<Vec<_>>();
+        due.sort_by_key(|entry| (entry.due, entry.id));
+
+        due.into_iter()
+            .map(|entry| {
+                let fired = Fired {
+                    thunk: Arc::clone(&entry.thunk),
+                    cancelled: Arc::clone(&entry.cancelled),
+                };
+                if let Some(period) = entry.period {
+                    // Periods that were missed entirely are skipped rather than fired all at once.
+                    let due = entry.due + ((to - entry.due) / period + 1) * period;
+                    self.insert(Entry { due, ..entry });
+                }
+                fired
+            })
+            .collect()
+    }
+
+    /// The tick that the next timer fires on.
+    fn next_due(&self) -> Option<u64> {
+        self.slots.iter().flatten().map(|entry| entry.due).min()
+    }
+
+    /// Remove every timer that `predicate` matches, returning whether any were removed.
+    fn cancel<P>(&mut self, mut predicate: P) -> bool
+    where
+        P: FnMut(&Entry) -> bool,
+    {
+        let mut removed = false;
+        self.slots.iter_mut().for_each(|slot| {
+            slot.retain(|entry| {
+                let remove = predicate(entry);
+                if remove {
+                    entry.cancelled.store(true, Ordering::Release);
+                    removed = true;
+                }
+                !remove
+            })
+        });
+        removed
+    }
+}
+
+/// Spawn the thread that fires timers, whose thunks are run on `executor`.
+///
+/// Nothing is fired by the thread while the fake clock is in use.
+/// The thread exits, dropping `executor`, once [stop] is called.
+pub fn spawn(executor: Executor) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut timers = TIMERS.lock();
+        loop {
+            if timers.stopping {
+                timers.stopping = false;
+                return;
+            }
+            let Clock::System(start) = timers.clock else {
+                CHANGED.wait(&mut timers);
+                continue;
+            };
+
+            timers
+                .fire()
+                .into_iter()
+                .for_each(|fired| executor.spawn_job(Priority::Bulk, move |api| fired.run(api)));
+
+            match timers.wheel.next_due() {
+                Some(due) => {
+                    CHANGED.wait_until(&mut timers, start + duration(due));
+                }
+                None => CHANGED.wait(&mut timers),
+            }
+        }
+    })
+}
+
+/// Make the thread started with [spawn] exit without firing any more timers.
+pub fn stop() {
+    TIMERS.lock().stopping = true;
+    CHANGED.notify_all();
+}
+
+/// Stop time from passing on its own, so it can be moved forward with [advance].
+///
+/// The fake clock starts on the next tick, so timers fire exactly when the clock reaches them.
+pub fn use_fake_clock() {
+    let mut timers = TIMERS.lock();
+    let elapsed = ticks_ceil(timers.clock.elapsed());
+    timers.clock = Clock::Fake(duration(elapsed));
+    CHANGED.notify_all();
+}
+
+/// Move the fake clock forward by `by` and call every timer that became due on this thread.
+///
+/// Returns the errors of the thunks that failed.
+///
+/// # Panics
+///
+/// Panics if [use_fake_clock] has not been called.
+pub fn advance(api: &mut Api, by: Duration) -> Vec<GuileError> {
+    let fired = {
+        let mut timers = TIMERS.lock();
+        let Clock::Fake(elapsed) = &mut timers.clock else {
+            panic!("the fake clock is not in use");
+        };
+        *elapsed += by;
+        timers.fire()
+    };
+
+    fired
+        .into_iter()
+        .filter_map(|fired| fired.run(api).err())
+        .collect()
+}
+
+impl Api {
+    /// Call `load`, which is expected to schedule a new set of timers, and cancel the timers from before it was called.
+    ///
+    /// The timers scheduled by `load` are cancelled instead if it fails.
+    pub fn replace_timers<F, T, E>(&mut self, load: F) -> Result<T, E>
+    where
+        F: FnOnce(&mut Self) -> Result<T, E>,
+    {
+        let previous = {
+            let mut timers = TIMERS.lock();
+            timers.generation += 1;
+            timers.generation - 1
+        };
+
+        let output = load(self);
+        let keep_new = output.is_ok();
+        TIMERS
+            .lock()
+            .wheel
+            .cancel(|entry| (entry.generation <= previous) == keep_new);
+
+        output
+    }
+}
+
+/// Parse a delay in seconds, which must be positive unless `allow_zero` is set.
+fn delay(api: &Api, seconds: f64, allow_zero: bool) -> Result<Duration, GuileError> {
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|delay| allow_zero || !delay.is_zero())
+        .ok_or_else(|| GuileError::out_of_range(api, seconds.into_scm(api)))
+}
+
+fn protect_thunk(api: &Api, thunk: Scm) -> Result<ProtectedScm, GuileError> {
+    if thunk.is_procedure() {
+        Ok(ProtectedScm::new(api, thunk))
+    } else {
+        Err(GuileError::wrong_type_arg(api, "procedure", thunk))
+    }
+}
+
+/// A timer that never fires, for configs that are being checked.
+fn recorded_timer() -> Timer {
+    Timer {
+        id: u64::MAX,
+        cancelled: Arc::new(AtomicBool::new(true)),
+    }
+}
+
+/// Call `thunk` once, `seconds` from now.
+///
+/// Returns a timer that can be passed to `cancel-timer`.
+#[guile_fn(module = "empl timers")]
+fn run_after(api: &mut Api, seconds: f64, thunk: Scm) -> Result<Timer, GuileError> {
+    let delay = delay(api, seconds, true)?;
+    let thunk = protect_thunk(api, thunk)?;
+    if check::record_effect(|| format!("call a thunk after {seconds} seconds")) {
+        return Ok(recorded_timer());
+    }
+
+    Ok(TIMERS.lock().schedule(delay, None, thunk))
+}
+
+/// Call `thunk` every `seconds`, starting `seconds` from now.
+///
+/// Returns a timer that can be passed to `cancel-timer`.
+/// Errors in `thunk` are reported without cancelling the timer.
+#[guile_fn(module = "empl timers")]
+fn run_every(api: &mut Api, seconds: f64, thunk: Scm) -> Result<Timer, GuileError> {
+    let period = delay(api, seconds, false)?;
+    let thunk = protect_thunk(api, thunk)?;
+    if check::record_effect(|| format!("call a thunk every {seconds} seconds")) {
+        return Ok(recorded_timer());
+    }
+
+    Ok(TIMERS.lock().schedule(period, Some(period), thunk))
+}
+
+/// Stop `timer` from firing again.
+///
+/// Returns `#f` if the timer had already fired or been cancelled.
+#[guile_fn(module = "empl timers")]
+fn cancel_timer(_: &mut Api, timer: &Timer) -> bool {
+    !timer.cancelled.load(Ordering::Acquire)
+        && TIMERS.lock().wheel.cancel(|entry| entry.id == timer.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            guile::{self, convert::FromScm},
+            tests::{ENV_VAR_LOCK, GUILE_STATE_LOCK},
+        },
+    };
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn wheel() {
+        let _lock = ENV_VAR_LOCK.read();
+
+        let mut wheel = Wheel::new();
+        let entry = |id, due, period| Entry {
+            id,
+            due,
+            period,
+            generation: 0,
+            thunk: Arc::new(guile::with_guile(|api| {
+                ProtectedScm::new(api, api.make_false())
+            })),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        wheel.insert(entry(0, 3, None));
+        wheel.insert(entry(1, 3 + SLOTS as u64, None));
+        wheel.insert(entry(2, 2, Some(5)));
+
+        assert_eq!(wheel.next_due(), Some(2));
+        assert_eq!(wheel.advance(1).len(), 0);
+        assert_eq!(wheel.advance(3).len(), 2);
+        assert_eq!(wheel.next_due(), Some(7));
+
+        // Going around the wheel fires the timer in the same slot, and skips missed periods.
+        assert_eq!(wheel.advance(3 + SLOTS as u64).len(), 2);
+        assert_eq!(wheel.next_due(), Some(517));
+
+        assert!(wheel.cancel(|entry| entry.id == 2));
+        assert_eq!(wheel.next_due(), None);
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn timers() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        guile::with_guile(|api| {
+            use_fake_clock();
+            api.define_bindings();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl timers))
+                    (define calls '())
+                    (define (call! name) (lambda () (set! calls (cons name calls))))
+                    (define once (run-after 1.5 (call! 'once)))
+                    (define every (run-every 1 (call! 'every)))
+                    (define never (run-after 1 (call! 'never))))",
+            );
+            let calls = |api: &mut Api| {
+                Vec::<String>::from_scm(
+                    api,
+                    api.eval_cstring(c"(reverse (map symbol->string calls))"),
+                )
+                .unwrap()
+            };
+
+            assert!(api.eval_cstring(c"(cancel-timer never)").is_true());
+            assert!(!api.eval_cstring(c"(cancel-timer never)").is_true());
+
+            assert!(advance(api, Duration::from_millis(999)).is_empty());
+            assert!(calls(api).is_empty());
+            assert!(advance(api, Duration::from_millis(1)).is_empty());
+            assert_eq!(calls(api), ["every"]);
+            assert!(advance(api, Duration::from_secs(1)).is_empty());
+            assert_eq!(calls(api), ["every", "once", "every"]);
+
+            assert!(!api.eval_cstring(c"(cancel-timer once)").is_true());
+            assert!(api.eval_cstring(c"(cancel-timer every)").is_true());
+            assert!(advance(api, Duration::from_secs(10)).is_empty());
+            assert_eq!(calls(api), ["every", "once", "every"]);
+
+            let error = api
+                .try_eval_cstring(c"(run-every 0 (lambda () #t))")
+                .unwrap_err();
+            assert_eq!(error.key(), "out-of-range");
+            let error = api.try_eval_cstring(c"(run-after 1 'foo)").unwrap_err();
+            assert_eq!(error.key(), "wrong-type-arg");
+        });
+    }
+
+    #[cfg_attr(miri, ignore)]
+    #[test]
+    fn replace_timers() {
+        let _lock = ENV_VAR_LOCK.read();
+        let _state_lock = GUILE_STATE_LOCK.write();
+
+        guile::with_guile(|api| {
+            use_fake_clock();
+            api.define_bindings();
+            api.eval_cstring(
+                c"(begin
+                    (use-modules (empl timers))
+                    (define fired '())
+                    (run-after 1 (lambda () (set! fired (cons 'old fired)))))",
+            );
+
+            api.replace_timers(|api| {
+                api.eval_cstring(c"(run-after 1 (lambda () (set! fired (cons 'failed fired))))");
+                Err::<(), _>(())
+            })
+            .unwrap_err();
+            api.replace_timers(|api| {
+                api.eval_cstring(c"(run-after 1 (lambda () (set! fired (cons 'new fired))))");
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+            assert!(advance(api, Duration::from_secs(1)).is_empty());
+            assert!(api.eval_cstring(c"(equal? fired '(new))").is_true());
+        });
+    }
+}